@@ -0,0 +1,83 @@
+// a BK-tree of 64 bit hashes under the hamming distance, a lookup only walks into the children
+// whose edge can still lead to a match by the triangle inequality
+pub struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+struct BkNode {
+    hash: u64,
+    // everything inserted with exactly this hash
+    values: Vec<usize>,
+    // (distance to this node, index of the child)
+    children: Vec<(u32, usize)>,
+}
+
+impl BkTree {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    pub fn insert(&mut self, hash: u64, value: usize) {
+        let mut current = 0;
+        while current < self.nodes.len() {
+            let distance = (self.nodes[current].hash ^ hash).count_ones();
+            if distance == 0 {
+                self.nodes[current].values.push(value);
+                return;
+            }
+            match self.nodes[current].children.iter().find(|(edge, _)| *edge == distance) {
+                Some((_, child)) => current = *child,
+                None => {
+                    let index = self.nodes.len();
+                    self.nodes[current].children.push((distance, index));
+                    break;
+                }
+            }
+        }
+        self.nodes.push(BkNode { hash, values: vec![value], children: Vec::new() });
+    }
+
+    // everything whose hash is at most distance bits away
+    pub fn find(&self, hash: u64, distance: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() { Vec::new() } else { vec![0] };
+        while let Some(index) = pending.pop() {
+            let node = &self.nodes[index];
+            let d = (node.hash ^ hash).count_ones();
+            if d <= distance {
+                found.extend_from_slice(&node.values);
+            }
+            pending.extend(node.children.iter().filter(|(edge, _)| edge.abs_diff(d) <= distance).map(|(_, child)| *child));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BkTree;
+
+    #[test]
+    fn finds_what_a_full_scan_finds() {
+        // a fixed xorshift so the hashes are spread and the run is repeatable, with every tenth one near the one before
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let mut hashes: Vec<u64> = Vec::new();
+        for i in 0..2000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            hashes.push(if i % 10 == 9 { hashes[i - 1] ^ (state & 0x0101) } else { state });
+        }
+        let mut tree = BkTree::new();
+        hashes.iter().enumerate().for_each(|(i, hash)| tree.insert(*hash, i));
+
+        for distance in [0, 2, 4, 16] {
+            for (i, hash) in hashes.iter().enumerate().step_by(7) {
+                let mut found = tree.find(*hash, distance);
+                found.sort_unstable();
+                let expected: Vec<usize> = (0..hashes.len()).filter(|j| (hashes[*j] ^ hash).count_ones() <= distance).collect();
+                assert_eq!(found, expected, "hash {} within {}", i, distance);
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::common::color::to_linear;
+use std::f64::consts::PI;
+
+const CHARACTERS: &[u8; 83] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+// the blurhash of w x h rgb pixels, row by row, with x by y cosine components, each 1 to 9
+pub fn encode(pixels: &[[u8; 3]], w: usize, h: usize, x: usize, y: usize) -> String {
+    let mut factors = Vec::with_capacity(x * y);
+    for j in 0..y {
+        for i in 0..x {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0; 3];
+            for py in 0..h {
+                for px in 0..w {
+                    let basis = (PI * i as f64 * px as f64 / w as f64).cos() * (PI * j as f64 * py as f64 / h as f64).cos();
+                    let pixel = pixels[py * w + px];
+                    (0..3).for_each(|c| factor[c] += basis * to_linear(pixel[c]));
+                }
+            }
+            factors.push(factor.map(|v| v * normalisation / (w * h) as f64));
+        }
+    }
+
+    let mut hash = String::new();
+    push(&mut hash, (x - 1) + (y - 1) * 9, 1);
+    let (dc, ac) = factors.split_first().unwrap();
+    let maximum = match ac.iter().flatten().map(|v| v.abs()).reduce(f64::max) {
+        Some(actual) => {
+            let quantised = (actual * 166.0 - 0.5).floor().clamp(0.0, 82.0) as usize;
+            push(&mut hash, quantised, 1);
+            (quantised + 1) as f64 / 166.0
+        }
+        None => {
+            push(&mut hash, 0, 1);
+            1.0
+        }
+    };
+    push(&mut hash, dc.iter().fold(0, |value, v| (value << 8) + to_srgb(*v)), 4);
+    for factor in ac {
+        let quantised = factor.map(|v| {
+            let v = v / maximum;
+            (v.signum() * v.abs().sqrt() * 9.0 + 9.5).floor().clamp(0.0, 18.0) as usize
+        });
+        push(&mut hash, quantised[0] * 19 * 19 + quantised[1] * 19 + quantised[2], 2);
+    }
+    hash
+}
+
+// base 83, most significant digit first
+fn push(hash: &mut String, value: usize, length: u32) {
+    for i in (0..length).rev() {
+        hash.push(CHARACTERS[value / 83usize.pow(i) % 83] as char);
+    }
+}
+
+fn to_srgb(v: f64) -> usize {
+    let v = v.clamp(0.0, 1.0);
+    let v = if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+    (v * 255.0 + 0.5) as usize
+}
@@ -0,0 +1,82 @@
+use std::cmp::Reverse;
+
+const PALETTE_ROUNDS: usize = 12;
+
+// k-means over the pixels, the clusters come back largest first as [r, g, b, a]
+pub fn palette(pixels: &[[u8; 4]], count: usize) -> Vec<[u8; 4]> {
+    // mostly transparent pixels are background, not color
+    let pixels: Vec<[f64; 4]> = pixels.iter()
+        .filter(|p| p[3] >= 128)
+        .map(|p| [p[0] as f64, p[1] as f64, p[2] as f64, p[3] as f64])
+        .collect();
+    if pixels.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    // seeded along the brightness order so the result does not depend on chance
+    let mut by_luma: Vec<&[f64; 4]> = pixels.iter().collect();
+    by_luma.sort_by(|lhs, rhs| luma(lhs).total_cmp(&luma(rhs)));
+    let mut centers: Vec<[f64; 4]> = (0..count)
+        .map(|i| *by_luma[(2 * i + 1) * by_luma.len() / (2 * count)])
+        .collect();
+    centers.dedup();
+
+    let mut sizes = vec![0; centers.len()];
+    for _ in 0..PALETTE_ROUNDS {
+        let mut sums = vec![[0.0; 4]; centers.len()];
+        sizes = vec![0; centers.len()];
+        for pixel in &pixels {
+            let nearest = nearest(&centers, pixel);
+            sizes[nearest] += 1;
+            (0..4).for_each(|c| sums[nearest][c] += pixel[c]);
+        }
+        let mut moved = false;
+        for (i, sum) in sums.iter().enumerate() {
+            if sizes[i] > 0 {
+                let center = sum.map(|v| v / sizes[i] as f64);
+                moved |= center != centers[i];
+                centers[i] = center;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    let mut clusters: Vec<(usize, [f64; 4])> = sizes.into_iter().zip(centers).filter(|(size, _)| *size > 0).collect();
+    clusters.sort_by_key(|(size, _)| Reverse(*size));
+    clusters.into_iter().map(|(_, center)| center.map(|v| v.round() as u8)).collect()
+}
+
+// CIE76 delta E, around 2 is just noticeable, 10 and more reads as another color
+pub fn distance(lhs: [u8; 3], rhs: [u8; 3]) -> f64 {
+    let (l1, a1, b1) = lab(lhs);
+    let (l2, a2, b2) = lab(rhs);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+fn nearest(centers: &[[f64; 4]], pixel: &[f64; 4]) -> usize {
+    let d = |center: &[f64; 4]| (0..3).map(|c| (center[c] - pixel[c]).powi(2)).sum::<f64>();
+    (0..centers.len()).min_by(|lhs, rhs| d(&centers[*lhs]).total_cmp(&d(&centers[*rhs]))).unwrap()
+}
+
+fn luma(p: &[f64; 4]) -> f64 {
+    0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2]
+}
+
+// an sRGB channel as linear light in 0 to 1
+pub fn to_linear(v: u8) -> f64 {
+    let v = v as f64 / 255.0;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+// sRGB to CIE L*a*b* under D65
+fn lab(rgb: [u8; 3]) -> (f64, f64, f64) {
+    let linear = rgb.map(to_linear);
+    let x = (0.4124 * linear[0] + 0.3576 * linear[1] + 0.1805 * linear[2]) / 0.95047;
+    let y = 0.2126 * linear[0] + 0.7152 * linear[1] + 0.0722 * linear[2];
+    let z = (0.0193 * linear[0] + 0.1192 * linear[1] + 0.9505 * linear[2]) / 1.08883;
+    let f = |t: f64| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
@@ -0,0 +1,108 @@
+use crate::common::{FileNode, Node};
+use chrono::NaiveDateTime;
+use exif::{Field, In, Reader, Tag, Value};
+use std::fs::File;
+use std::io::BufReader;
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Exif {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    // in seconds
+    pub exposure_time: Option<f64>,
+    pub f_number: Option<f64>,
+    pub iso: Option<u32>,
+    // in millimeters
+    pub focal_length: Option<f64>,
+    // camera local time, exif keeps no zone for it in most files
+    pub taken_at: Option<NaiveDateTime>,
+    // 1 to 8 as in the exif spec, 1 is upright
+    pub orientation: Option<u16>,
+    pub gps: Option<Gps>,
+}
+
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct Gps {
+    // in degrees, south and west are negative
+    pub latitude: f64,
+    pub longitude: f64,
+    // in meters above sea level
+    pub altitude: Option<f64>,
+}
+
+// a file without exif, or with a broken block, simply has none
+pub fn read_exif(file: &FileNode) -> Option<Exif> {
+    let mut reader = BufReader::new(File::open(file.absolute_path()).ok()?);
+    let exif = Reader::new().read_from_container(&mut reader).ok()?;
+    let field = |tag: Tag| exif.get_field(tag, In::PRIMARY);
+
+    Some(Exif {
+        make: field(Tag::Make).and_then(ascii),
+        model: field(Tag::Model).and_then(ascii),
+        lens: field(Tag::LensModel).and_then(ascii),
+        exposure_time: field(Tag::ExposureTime).and_then(rational),
+        f_number: field(Tag::FNumber).and_then(rational),
+        iso: field(Tag::PhotographicSensitivity).and_then(|f| f.value.get_uint(0)),
+        focal_length: field(Tag::FocalLength).and_then(rational),
+        taken_at: field(Tag::DateTimeOriginal).or(field(Tag::DateTime)).and_then(date_time),
+        orientation: field(Tag::Orientation).and_then(|f| f.value.get_uint(0)).map(|v| v as u16),
+        gps: gps(&field),
+    })
+}
+
+fn gps<'a>(field: &impl Fn(Tag) -> Option<&'a Field>) -> Option<Gps> {
+    let latitude = degree(field(Tag::GPSLatitude)?)? * sign(field(Tag::GPSLatitudeRef), b'S');
+    let longitude = degree(field(Tag::GPSLongitude)?)? * sign(field(Tag::GPSLongitudeRef), b'W');
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return None;
+    }
+    let below_sea = field(Tag::GPSAltitudeRef).and_then(|f| f.value.get_uint(0)) == Some(1);
+    let altitude = field(Tag::GPSAltitude).and_then(rational).map(|v| if below_sea { -v } else { v });
+    Some(Gps { latitude, longitude, altitude })
+}
+
+// degrees, minutes and seconds
+fn degree(field: &Field) -> Option<f64> {
+    match &field.value {
+        Value::Rational(values) if values.len() == 3 && values.iter().all(|v| v.denom != 0) => {
+            Some(values[0].to_f64() + values[1].to_f64() / 60.0 + values[2].to_f64() / 3600.0)
+        }
+        _ => None,
+    }
+}
+
+fn sign(field: Option<&Field>, negative: u8) -> f64 {
+    match field.map(|f| &f.value) {
+        Some(Value::Ascii(values)) if values.first().and_then(|v| v.first()) == Some(&negative) => -1.0,
+        _ => 1.0,
+    }
+}
+
+fn ascii(field: &Field) -> Option<String> {
+    match &field.value {
+        Value::Ascii(values) => values.first()
+            .map(|v| String::from_utf8_lossy(v).trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string())
+            .filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
+fn rational(field: &Field) -> Option<f64> {
+    match &field.value {
+        Value::Rational(values) => values.first().filter(|v| v.denom != 0).map(|v| v.to_f64()),
+        Value::SRational(values) => values.first().filter(|v| v.denom != 0).map(|v| v.to_f64()),
+        _ => None,
+    }
+}
+
+fn date_time(field: &Field) -> Option<NaiveDateTime> {
+    match &field.value {
+        Value::Ascii(values) => {
+            let dt = exif::DateTime::from_ascii(values.first()?).ok()?;
+            chrono::NaiveDate::from_ymd_opt(dt.year as i32, dt.month as u32, dt.day as u32)?
+                .and_hms_opt(dt.hour as u32, dt.minute as u32, dt.second as u32)
+        }
+        _ => None,
+    }
+}
@@ -1,19 +1,21 @@
 use crate::common::Error::NoSuchFile;
 use crate::common::{json, ContentType, Error, Res};
+use actix_web::rt::task::{spawn_blocking, JoinHandle};
 use bytes::Bytes;
 use futures_core::Stream;
 use serde::de;
-use std::cell::RefCell;
 use std::fs;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fs::{File, OpenOptions};
+use std::cmp::min;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::ops::Add;
 use std::path::Path;
+use std::future::Future;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex, RwLock};
 use std::task::Poll::Ready;
-use std::task::{Context, Poll};
-use log::debug;
+use std::task::{ready, Context, Poll};
+use std::time::SystemTime;
 
 static FILE_SEPARATOR: &str = if cfg!(target_os = "windows") {
     "\\"
@@ -111,7 +113,7 @@ pub struct DirNode {
 pub struct FileNode {
     node: Arc<RwLock<(String, Option<DirNode>)>>,
     content_type: &'static ContentType,
-    file: Arc<RefCell<Option<File>>>,
+    file: Arc<Mutex<Option<File>>>,
 }
 
 impl Node for DirNode {
@@ -217,23 +219,37 @@ impl DirNode {
         FileNode {
             node: Arc::new(RwLock::new((name, Some(self.clone())))),
             content_type: context_type,
-            file: Arc::new(RefCell::new(None)),
+            file: Arc::new(Mutex::new(None)),
         }
     }
+
+    pub fn list_files(&self) -> Res<Vec<FileNode>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(self.absolute_path()).map_err(warp_e)? {
+            let entry = entry.map_err(warp_e)?;
+            if entry.file_type().map_err(warp_e)?.is_file() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let (name, ext) = match name.split_once('.') {
+                    None => (name.as_str(), &content_type::UNKNOWN),
+                    Some((name, ext)) => (name, content_type::guess(&format!(".{}", ext))),
+                };
+                files.push(self.to(String::from(name), ext));
+            }
+        }
+        Ok(files)
+    }
 }
 
 impl FileNode {
     pub fn touch(&self) -> Res<()> {
         self.up().unwrap().mkdir()?;
-        self.file.replace(Option::from(
-            File::create(self.absolute_path()).map_err(warp_e)?,
-        ));
+        *self.file.lock()? = Some(File::create(self.absolute_path()).map_err(warp_e)?);
         Ok(())
     }
 
     pub fn read(&mut self, buf: &mut [u8]) -> Res<usize> {
         self.open_file(false)?;
-        match self.file.try_borrow_mut()?.as_mut() {
+        match self.file.lock()?.as_mut() {
             None => Err(warp("File not exists")),
             Some(file) => file.read(buf).map_err(warp_e),
         }
@@ -250,7 +266,7 @@ impl FileNode {
     }
 
     pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Res<usize> {
-        match self.file.try_borrow_mut()?.as_mut() {
+        match self.file.lock()?.as_mut() {
             None => Err(warp("File not exists")),
             Some(file) => file.read_to_end(buf).map_err(warp_e),
         }
@@ -261,7 +277,7 @@ impl FileNode {
         T: de::DeserializeOwned,
     {
         self.open_file(true)?;
-        match self.file.try_borrow_mut()?.as_mut() {
+        match self.file.lock()?.as_mut() {
             None => Err(warp("File not exists")),
             Some(file) => {
                 let mut buf = String::new();
@@ -272,11 +288,19 @@ impl FileNode {
     }
 
     pub fn as_stream(&self) -> Res<FileStream> {
+        self.as_range_stream(0, u64::MAX)
+    }
+
+    // at most len bytes starting from start
+    pub fn as_range_stream(&self, start: u64, len: u64) -> Res<FileStream> {
         self.open_file(true)?;
-        let file = self.file.replace(None);
-        if let Some(file) = file {
+        let file = self.file.lock()?.take();
+        if let Some(mut file) = file {
+            file.seek(SeekFrom::Start(start)).map_err(warp_e)?;
             Ok(FileStream {
-                file: RefCell::new(file),
+                file: Some(file),
+                remaining: len,
+                reading: None,
             })
         } else {
             Err(NoSuchFile)
@@ -285,20 +309,64 @@ impl FileNode {
 
     pub fn write(&mut self, buf: &[u8]) -> Res<usize> {
         self.touch()?;
-        match self.file.try_borrow_mut()?.as_mut() {
+        match self.file.lock()?.as_mut() {
             None => Err(warp("File not exists")),
             Some(file) => file.write(buf).map_err(warp_e),
         }
     }
 
+    // keeps writing to the handle opened by touch instead of truncating the file
+    pub fn append(&mut self, buf: &[u8]) -> Res<()> {
+        match self.file.lock()?.as_mut() {
+            None => Err(warp("File not exists")),
+            Some(file) => file.write_all(buf).map_err(warp_e),
+        }
+    }
+
+    pub fn move_to_file(&self, target: &FileNode) -> Res<()> {
+        self.file.lock()?.take();
+        target.up().unwrap().mkdir()?;
+        fs::rename(self.absolute_path(), target.absolute_path()).map_err(warp_e)
+    }
+
+    pub fn copy_to_file(&self, target: &FileNode) -> Res<()> {
+        target.up().unwrap().mkdir()?;
+        fs::copy(self.absolute_path(), target.absolute_path()).map(|_| ()).map_err(warp_e)
+    }
+
+    // opens for appending and returns the current length
+    pub fn open_append(&self) -> Res<u64> {
+        let file = OpenOptions::new().append(true).open(self.absolute_path()).map_err(warp_e)?;
+        let len = file.metadata().map_err(warp_e)?.len();
+        *self.file.lock()? = Some(file);
+        Ok(len)
+    }
+
+    pub fn size(&self) -> Res<u64> {
+        Ok(fs::metadata(self.absolute_path()).map_err(warp_e)?.len())
+    }
+
+    pub fn modified(&self) -> Res<SystemTime> {
+        fs::metadata(self.absolute_path()).and_then(|metadata| metadata.modified()).map_err(warp_e)
+    }
+
+    pub fn remove(&self) -> Res<()> {
+        self.file.lock()?.take();
+        fs::remove_file(self.absolute_path()).map_err(warp_e)
+    }
+
     pub fn set_content_type(&mut self, content_type: &'static ContentType) {
         self.content_type = content_type;
     }
 
+    pub fn content_type(&self) -> &'static ContentType {
+        self.content_type
+    }
+
     fn open_file(&self, reopen: bool) -> Res<()> {
-        if self.file.borrow().is_none() || reopen {
-            self.file
-                .replace(Some(File::open(self.absolute_path()).map_err(warp_e)?));
+        let mut file = self.file.lock()?;
+        if file.is_none() || reopen {
+            *file = Some(File::open(self.absolute_path()).map_err(warp_e)?);
         }
         Ok(())
     }
@@ -350,6 +418,18 @@ pub mod content_type {
         Unknown,
         Plain,
         Image,
+        Vector,
+        Video,
+        Document,
+        Archive,
+    }
+
+    pub enum Magic {
+        None,
+        // every (offset, bytes) part has to match
+        Bytes(&'static [(usize, &'static [u8])]),
+        // iso-bmff containers, matched against the brands of the ftyp box
+        Brands(&'static [&'static [u8; 4]]),
     }
 
     pub struct ContentType {
@@ -357,41 +437,109 @@ pub mod content_type {
         pub file: FileType,
         pub ext: &'static str,
         pub mimetype: &'static str,
-        pub magic: &'static [u8],
-    }
-
-    static_file_context_type!(0x000, UNKNOWN, FileType::Unknown, "", "application/octet-stream", &[]);
-    static_file_context_type!(0x001, JSON, FileType::Plain, ".json", "application/json", &[]);
-    static_file_context_type!(0x002, GIF87A, FileType::Image, ".gif", "image/gif", &[0x47, 0x49, 0x46, 0x38, 0x37, 0x61]);
-    static_file_context_type!(0x003, GIF89A, FileType::Image, ".gif", "image/gif", &[0x47, 0x49, 0x46, 0x38, 0x39, 0x61]);
-    static_file_context_type!(0x004, BMP, FileType::Image, ".bmp", "image/bmp", &[0x42, 0x4D]);
-    static_file_context_type!(0x005, JPEG, FileType::Image, ".jpg", "image/jpeg", &[0xFF, 0xD8, 0xFF]);
-    static_file_context_type!(0x006, PNG, FileType::Image, ".png", "image/png", &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]);
-    static_file_context_type!(0x007, HEIF, FileType::Image, ".heif", "image/heif", &[0x66, 0x74, 0x79, 0x70]);
-    static_file_context_type!(0x008, TIFF1, FileType::Image, ".tif", "image/tiff", &[0x49, 0x49, 0x2A, 0x00]);
-    static_file_context_type!(0x009, TIFF2, FileType::Image, ".tiff", "image/tiff", &[0x4D, 0x4D, 0x00, 0x2A]);
+        pub magic: Magic,
+    }
+
+    static_file_context_type!(0x000, UNKNOWN, FileType::Unknown, "", "application/octet-stream", Magic::None);
+    static_file_context_type!(0x001, JSON, FileType::Plain, ".json", "application/json", Magic::None);
+    static_file_context_type!(0x002, GIF87A, FileType::Image, ".gif", "image/gif", Magic::Bytes(&[(0, &[0x47, 0x49, 0x46, 0x38, 0x37, 0x61])]));
+    static_file_context_type!(0x003, GIF89A, FileType::Image, ".gif", "image/gif", Magic::Bytes(&[(0, &[0x47, 0x49, 0x46, 0x38, 0x39, 0x61])]));
+    static_file_context_type!(0x004, BMP, FileType::Image, ".bmp", "image/bmp", Magic::Bytes(&[(0, &[0x42, 0x4D])]));
+    static_file_context_type!(0x005, JPEG, FileType::Image, ".jpg", "image/jpeg", Magic::Bytes(&[(0, &[0xFF, 0xD8, 0xFF])]));
+    static_file_context_type!(0x006, PNG, FileType::Image, ".png", "image/png", Magic::Bytes(&[(0, &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A])]));
+    static_file_context_type!(0x007, HEIF, FileType::Image, ".heif", "image/heif", Magic::Brands(&[b"mif1", b"msf1", b"heif"]));
+    static_file_context_type!(0x008, TIFF1, FileType::Image, ".tif", "image/tiff", Magic::Bytes(&[(0, &[0x49, 0x49, 0x2A, 0x00])]));
+    static_file_context_type!(0x009, TIFF2, FileType::Image, ".tiff", "image/tiff", Magic::Bytes(&[(0, &[0x4D, 0x4D, 0x00, 0x2A])]));
+    static_file_context_type!(0x00A, WEBP, FileType::Image, ".webp", "image/webp", Magic::Bytes(&[(0, b"RIFF"), (8, b"WEBP")]));
+    static_file_context_type!(0x00B, AVIF, FileType::Image, ".avif", "image/avif", Magic::Brands(&[b"avif", b"avis"]));
+    static_file_context_type!(0x00C, SVG, FileType::Vector, ".svg", "image/svg+xml", Magic::None);
+    static_file_context_type!(0x00D, PDF, FileType::Document, ".pdf", "application/pdf", Magic::Bytes(&[(0, b"%PDF-")]));
+    static_file_context_type!(0x00E, MP4, FileType::Video, ".mp4", "video/mp4", Magic::Brands(&[b"isom", b"iso2", b"iso4", b"iso5", b"iso6", b"mp41", b"mp42", b"avc1", b"dash"]));
+    static_file_context_type!(0x00F, TEXT, FileType::Plain, ".txt", "text/plain; charset=utf-8", Magic::None);
+    static_file_context_type!(0x010, ZIP, FileType::Archive, ".zip", "application/zip", Magic::Bytes(&[(0, &[0x50, 0x4B, 0x03, 0x04])]));
+    static_file_context_type!(0x011, GZIP, FileType::Archive, ".gz", "application/gzip", Magic::Bytes(&[(0, &[0x1F, 0x8B])]));
+    static_file_context_type!(0x012, SEVEN_ZIP, FileType::Archive, ".7z", "application/x-7z-compressed", Magic::Bytes(&[(0, &[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C])]));
+    static_file_context_type!(0x013, RAR, FileType::Archive, ".rar", "application/vnd.rar", Magic::Bytes(&[(0, &[0x52, 0x61, 0x72, 0x21, 0x1A, 0x07])]));
+    static_file_context_type!(0x014, TAR, FileType::Archive, ".tar", "application/x-tar", Magic::Bytes(&[(257, b"ustar")]));
+    static_file_context_type!(0x015, HEIC, FileType::Image, ".heic", "image/heic", Magic::Brands(&[b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx"]));
+    static_file_context_type!(0x016, MOV, FileType::Video, ".mov", "video/quicktime", Magic::Brands(&[b"qt  "]));
 
     const ALL: &'static [ContentType] = &[
         UNKNOWN, JSON, GIF87A, GIF89A, BMP, JPEG, PNG, HEIF, TIFF1, TIFF2,
+        WEBP, AVIF, SVG, PDF, MP4, TEXT, ZIP, GZIP, SEVEN_ZIP, RAR, TAR, HEIC, MOV,
     ];
 
-    pub fn file_check(data: &Vec<u8>) -> Res<&'static ContentType> {
-        if data.len() < 8 {
+    // brands are shared, an avif also lists mif1 and a heic may list isom,
+    // so the most specific type comes first
+    const BRANDED: &[ContentType] = &[AVIF, HEIC, HEIF, MOV, MP4];
+
+    pub fn file_check(data: &[u8]) -> Res<&'static ContentType> {
+        if data.is_empty() {
             return Err(Error::UnknownFileContentType);
         }
 
         for content_type in ALL.iter() {
-            if content_type.magic.len() > 0
-                && data
-                    .iter()
-                    .zip(content_type.magic.iter())
-                    .all(|(d, m)| d == m)
-            {
-                return Ok(content_type);
+            if let Magic::Bytes(parts) = content_type.magic {
+                if parts.iter().all(|(offset, magic)| data.get(*offset..*offset + magic.len()) == Some(*magic)) {
+                    return Ok(content_type);
+                }
             }
         }
 
-        Err(Error::UnknownFileContentType)
+        if let Some(content_type) = brand_check(data) {
+            return Ok(content_type);
+        }
+
+        // formats without a magic number are told apart by their text
+        match text_check(data) {
+            Some(text) if is_svg(text) => Ok(&SVG),
+            Some(text) if is_note(text) => Ok(&TEXT),
+            _ => Err(Error::UnknownFileContentType),
+        }
+    }
+
+    // ftyp box: size(4) "ftyp"(4) major brand(4) minor version(4) compatible brands(4 * n)
+    fn brand_check(data: &[u8]) -> Option<&'static ContentType> {
+        if data.get(4..8) != Some(b"ftyp".as_slice()) {
+            return None;
+        }
+        let size = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+        let major = data.get(8..12)?;
+        let compatible = data.get(16..size.min(data.len())).unwrap_or_default();
+        let brands = compatible.chunks_exact(4).chain(std::iter::once(major));
+
+        BRANDED.iter().find(|content_type| match content_type.magic {
+            Magic::Brands(known) => brands.clone().any(|brand| known.iter().any(|b| b.as_slice() == brand)),
+            _ => false,
+        })
+    }
+
+    // data is only the head of the file, so a character cut at the end is still text
+    fn text_check(data: &[u8]) -> Option<&str> {
+        let text = match std::str::from_utf8(data) {
+            Ok(text) => text,
+            Err(e) if e.error_len().is_none() => std::str::from_utf8(&data[..e.valid_up_to()]).ok()?,
+            Err(_) => return None,
+        };
+        if text.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0C')) {
+            return None;
+        }
+        Some(text)
+    }
+
+    fn is_svg(text: &str) -> bool {
+        let text = text.trim_start_matches('\u{FEFF}').trim_start();
+        text.starts_with("<svg") || (text.starts_with("<?xml") && text.contains("<svg"))
+    }
+
+    // plain notes only, markup, data and scripts are turned away rather than stored as text
+    fn is_note(text: &str) -> bool {
+        const SCRIPT: &[&str] = &["#!", "//", "/*", "function", "var ", "let ", "const ", "import ", "export ",
+            "\"use strict\"", "'use strict'", "(function", "!function"];
+        let head = text.trim_start_matches('\u{FEFF}').trim_start();
+        !head.starts_with(['<', '{', '['])
+            && !SCRIPT.iter().any(|prefix| head.starts_with(prefix))
+            && !text.to_ascii_lowercase().contains("<script")
     }
 
     pub fn from(id: i64) -> &'static ContentType {
@@ -433,27 +581,49 @@ fn warp_e(e: std::io::Error) -> Error {
     Error::DirectoryError(e.to_string())
 }
 
+const STREAM_CHUNK_SIZE: u64 = 256 * 1024;
+
+// every read runs on the blocking pool, the file moves into the task and back so no worker ever waits on disk
 pub struct FileStream {
-    file: RefCell<File>,
+    file: Option<File>,
+    remaining: u64,
+    reading: Option<JoinHandle<std::io::Result<(File, Bytes)>>>,
 }
 
 impl Stream for FileStream {
     type Item = Res<Bytes>;
 
-    fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let mut buffer = [0; 1024];
-        let res = self.file.borrow_mut().read(&mut buffer);
-        if let Ok(size) = res {
-            if size == 0 {
-                Ready(None)
-            } else {
-                let mut vec = Vec::from(buffer);
-                vec.truncate(size);
-                let bytes = Bytes::from(vec);
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.as_mut().get_mut();
+        if this.reading.is_none() {
+            let file = match this.file.take() {
+                Some(file) if this.remaining > 0 => file,
+                _ => return Ready(None),
+            };
+            let size = min(STREAM_CHUNK_SIZE, this.remaining);
+            this.reading = Some(spawn_blocking(move || {
+                let mut buffer = Vec::with_capacity(size as usize);
+                (&file).take(size).read_to_end(&mut buffer)?;
+                Ok((file, Bytes::from(buffer)))
+            }));
+        }
+
+        let res = match this.reading.as_mut() {
+            Some(reading) => ready!(Pin::new(reading).poll(cx)),
+            None => return Ready(None),
+        };
+        this.reading = None;
+        match res {
+            Ok(Ok((file, bytes))) => {
+                if bytes.is_empty() {
+                    return Ready(None);
+                }
+                this.remaining -= bytes.len() as u64;
+                this.file = Some(file);
                 Ready(Some(Ok(bytes)))
             }
-        } else {
-            Ready(Some(Err(NoSuchFile)))
+            Ok(Err(e)) => Ready(Some(Err(warp_e(e)))),
+            Err(e) => Ready(Some(Err(Error::Busy(e.to_string())))),
         }
     }
 }
@@ -0,0 +1,27 @@
+// mean earth radius in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+// great circle distance in meters
+pub fn distance(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lng = (lng2 - lng1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+// (min_lat, max_lat, min_lng, max_lng) holding every point within radius meters,
+// min_lng > max_lng when the box crosses the antimeridian
+pub fn bounding_box(lat: f64, lng: f64, radius: f64) -> (f64, f64, f64, f64) {
+    let d_lat = (radius / EARTH_RADIUS).to_degrees();
+    let (min_lat, max_lat) = (lat - d_lat, lat + d_lat);
+    if min_lat <= -90.0 || max_lat >= 90.0 || d_lat >= 90.0 {
+        return (min_lat.max(-90.0), max_lat.min(90.0), -180.0, 180.0);
+    }
+    let d_lng = ((radius / EARTH_RADIUS).sin() / lat.to_radians().cos()).min(1.0).asin().to_degrees();
+    (min_lat, max_lat, normalize(lng - d_lng), normalize(lng + d_lng))
+}
+
+pub fn normalize(lng: f64) -> f64 {
+    (lng + 180.0).rem_euclid(360.0) - 180.0
+}
@@ -0,0 +1,21 @@
+use sha2::{Digest, Sha256};
+
+// sha256 fed chunk by chunk, finished as lowercase hex
+#[derive(Default)]
+pub struct ContentHasher {
+    hasher: Sha256,
+}
+
+impl ContentHasher {
+    pub fn new() -> Self {
+        Self { hasher: Sha256::new() }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    pub fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
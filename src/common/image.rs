@@ -1,22 +1,319 @@
-use crate::common::{Error, FileNode, Res};
+use crate::common::blurhash;
+use crate::common::color;
+use crate::common::file::content_type;
+use crate::common::{ContentType, Error, FileNode, Node, Res};
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::imageops::FilterType;
 use image::ImageError;
-use image::ImageFormat;
-use std::io::{Cursor, Write};
+use image::metadata::Orientation;
+use image::{AnimationDecoder, Delay, DynamicImage, Frame, Frames, ImageBuffer, ImageDecoder, ImageFormat, ImageReader};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek};
+use tiff::decoder::{Decoder as TiffDecoder, DecodingResult};
+use tiff::{ColorType, TiffError};
 
-pub fn get_size(data: &Vec<u8>) -> Res<(u32, u32)> {
-    let image = image::load_from_memory(data).map_err(warp_e)?;
-    Ok((image.width(), image.height()))
+const THUMBNAIL_SIZE: u32 = 300;
+
+// in milliseconds, an animated thumbnail stops after the frame crossing it
+const ANIMATED_THUMBNAIL_DURATION: u64 = 10_000;
+
+#[derive(Default, Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub enum Fit {
+    // scale into the box, keeping the aspect ratio
+    #[default]
+    Contain,
+    // scale until the box is filled, the overflow is cut off
+    Cover,
+    // cut the box out of the middle without scaling
+    Crop,
+}
+
+#[derive(Default, Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub enum RenditionFormat {
+    #[default]
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl RenditionFormat {
+    pub fn content_type(&self) -> &'static ContentType {
+        match self {
+            RenditionFormat::Png => &content_type::PNG,
+            RenditionFormat::Jpeg => &content_type::JPEG,
+            RenditionFormat::WebP => &content_type::WEBP,
+        }
+    }
+}
+
+// frames of an animation or pages of a tiff, duration is one loop in milliseconds
+#[derive(Clone, Copy)]
+pub struct FrameInfo {
+    pub count: u32,
+    pub duration: u64,
+    pub animated: bool,
+}
+
+impl Default for FrameInfo {
+    fn default() -> Self {
+        Self { count: 1, duration: 0, animated: false }
+    }
+}
+
+// avif and heif are sniffed and stored, but this build has no decoder for them,
+// the avif feature of image only encodes unless avif-native is on
+pub fn can_decode(content_type: &ContentType) -> bool {
+    match ImageFormat::from_mime_type(content_type.mimetype) {
+        None | Some(ImageFormat::Avif) => false,
+        Some(format) => format.reading_enabled(),
+    }
+}
+
+// only the header is decoded, the pixels stay on disk,
+// w and h are as the image is displayed, after the exif orientation
+pub fn get_size(file: &FileNode) -> Res<(u32, u32)> {
+    let mut decoder = ImageReader::open(file.absolute_path())?
+        .with_guessed_format()?
+        .into_decoder()
+        .map_err(warp_e)?;
+    let (w, h) = decoder.dimensions();
+    match decoder.orientation().unwrap_or(Orientation::NoTransforms) {
+        Orientation::Rotate90 | Orientation::Rotate270 |
+        Orientation::Rotate90FlipH | Orientation::Rotate270FlipH => Ok((h, w)),
+        _ => Ok((w, h)),
+    }
+}
+
+fn load(data: &[u8]) -> Res<DynamicImage> {
+    decode(ImageReader::new(Cursor::new(data)).with_guessed_format()?)
+}
+
+// decodes and turns the pixels upright
+fn decode<R: BufRead + Seek>(reader: ImageReader<R>) -> Res<DynamicImage> {
+    let mut decoder = reader.into_decoder().map_err(warp_e)?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(warp_e)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+// the frames and the upright pixels of the first one, for everything an upload looks at,
+// the file is streamed and an animation is walked once, its first frame is the image
+pub fn load_file(file: &FileNode) -> Res<(FrameInfo, DynamicImage)> {
+    let reader = ImageReader::new(open(file)?).with_guessed_format()?;
+    let format = reader.format().ok_or_else(|| Error::ImageLoadError(String::from("unknown image format")))?;
+    if let Some(frames) = animation(format, open(file)?)? {
+        let (mut count, mut duration, mut first) = (0, 0, None);
+        for frame in frames {
+            let frame = frame.map_err(warp_e)?;
+            count += 1;
+            duration += milliseconds(frame.delay());
+            first.get_or_insert_with(|| DynamicImage::ImageRgba8(frame.into_buffer()));
+        }
+        let image = first.ok_or(Error::NoSuchFrame(0))?;
+        return Ok((FrameInfo { count, duration, animated: count > 1 }, image));
+    }
+    let frames = match format {
+        ImageFormat::Tiff => FrameInfo { count: tiff_pages(open(file)?)?, ..FrameInfo::default() },
+        _ => FrameInfo::default(),
+    };
+    Ok((frames, decode(reader)?))
+}
+
+// one frame of an animation or one page of a tiff, index 0 of a still image is the image
+pub fn load_frame(data: &[u8], index: u32) -> Res<DynamicImage> {
+    if let Some(mut frames) = animation(image::guess_format(data).map_err(warp_e)?, Cursor::new(data))? {
+        return match frames.nth(index as usize) {
+            Some(frame) => Ok(DynamicImage::ImageRgba8(frame.map_err(warp_e)?.into_buffer())),
+            None => Err(Error::NoSuchFrame(index)),
+        };
+    }
+    match (image::guess_format(data).map_err(warp_e)?, index) {
+        (_, 0) => load(data),
+        (ImageFormat::Tiff, _) => tiff_page(Cursor::new(data), index),
+        _ => Err(Error::NoSuchFrame(index)),
+    }
+}
+
+fn open(file: &FileNode) -> Res<BufReader<File>> {
+    Ok(BufReader::new(File::open(file.absolute_path())?))
+}
+
+// gif, apng and animated webp, None for a still image
+fn animation<'a, R: BufRead + Seek + 'a>(format: ImageFormat, reader: R) -> Res<Option<Frames<'a>>> {
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(reader).map_err(warp_e)?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader).map_err(warp_e)?;
+            if !decoder.is_apng().map_err(warp_e)? {
+                return Ok(None);
+            }
+            decoder.apng().map_err(warp_e)?.into_frames()
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(reader).map_err(warp_e)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(frames))
+}
+
+fn milliseconds(delay: Delay) -> u64 {
+    let (numer, denom) = delay.numer_denom_ms();
+    numer as u64 / denom.max(1) as u64
+}
+
+// image reads only the first page of a tiff, the others go through the tiff crate
+fn tiff_pages<R: Read + Seek>(reader: R) -> Res<u32> {
+    let mut decoder = TiffDecoder::new(reader).map_err(warp_tiff)?;
+    let mut count = 1;
+    while decoder.more_images() {
+        decoder.next_image().map_err(warp_tiff)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn tiff_page<R: Read + Seek>(reader: R, index: u32) -> Res<DynamicImage> {
+    let mut decoder = TiffDecoder::new(reader).map_err(warp_tiff)?;
+    decoder.seek_to_image(index as usize).map_err(|_| Error::NoSuchFrame(index))?;
+    let (w, h) = decoder.dimensions().map_err(warp_tiff)?;
+    let image = match (decoder.colortype().map_err(warp_tiff)?, decoder.read_image().map_err(warp_tiff)?) {
+        (ColorType::Gray(8), DecodingResult::U8(buffer)) => ImageBuffer::from_raw(w, h, buffer).map(DynamicImage::ImageLuma8),
+        (ColorType::GrayA(8), DecodingResult::U8(buffer)) => ImageBuffer::from_raw(w, h, buffer).map(DynamicImage::ImageLumaA8),
+        (ColorType::RGB(8), DecodingResult::U8(buffer)) => ImageBuffer::from_raw(w, h, buffer).map(DynamicImage::ImageRgb8),
+        (ColorType::RGBA(8), DecodingResult::U8(buffer)) => ImageBuffer::from_raw(w, h, buffer).map(DynamicImage::ImageRgba8),
+        (ColorType::Gray(16), DecodingResult::U16(buffer)) => ImageBuffer::from_raw(w, h, buffer).map(DynamicImage::ImageLuma16),
+        (ColorType::GrayA(16), DecodingResult::U16(buffer)) => ImageBuffer::from_raw(w, h, buffer).map(DynamicImage::ImageLumaA16),
+        (ColorType::RGB(16), DecodingResult::U16(buffer)) => ImageBuffer::from_raw(w, h, buffer).map(DynamicImage::ImageRgb16),
+        (ColorType::RGBA(16), DecodingResult::U16(buffer)) => ImageBuffer::from_raw(w, h, buffer).map(DynamicImage::ImageRgba16),
+        _ => None,
+    };
+    image.ok_or_else(|| Error::ImageLoadError(format!("page {} of the tiff has an unsupported color type", index)))
+}
+
+// the few colors covering most of the image, largest first as [r, g, b, a]
+pub fn dominant_colors(image: &DynamicImage, count: usize) -> Vec<[u8; 4]> {
+    // a small copy has the same palette and keeps the clustering cheap
+    let image = image.thumbnail(64, 64).to_rgba8();
+    let pixels: Vec<[u8; 4]> = image.pixels().map(|p| p.0).collect();
+    color::palette(&pixels, count)
+}
+
+// dHash, each bit tells whether a pixel of a 9 x 8 grayscale copy is darker than its right neighbour,
+// resized or recompressed copies keep most of the bits
+pub fn perceptual_hash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = (hash << 1) | (small.get_pixel(x, y).0[0] < small.get_pixel(x + 1, y).0[0]) as u64;
+        }
+    }
+    hash
+}
+
+// a blurhash the clients paint until the thumbnail arrives, with more components along the longer side
+pub fn placeholder(image: &DynamicImage) -> String {
+    let image = image.thumbnail(32, 32).to_rgb8();
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let (x, y) = if w >= h { (4, 3) } else { (3, 4) };
+    let pixels: Vec<[u8; 3]> = image.pixels().map(|p| p.0).collect();
+    blurhash::encode(&pixels, w, h, x, y)
 }
 
 pub fn build_thumbnail(data: &Vec<u8>) -> Res<Vec<u8>> {
-    let image = image::load_from_memory(data).map_err(warp_e)?;
-    let thumbnail = image.thumbnail(300, 300);
+    let image = load(data)?;
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
     let mut buffer = Vec::new();
     let ref mut writer = Cursor::new(&mut buffer);
     thumbnail.write_to(writer, ImageFormat::Png).map_err(warp_e)?;
     Ok(buffer)
 }
 
+pub fn build_rendition(data: &[u8], w: u32, h: u32, fit: Fit, format: RenditionFormat) -> Res<Vec<u8>> {
+    let image = load(data)?;
+    let image = match fit {
+        Fit::Contain => image.resize(w, h, FilterType::Lanczos3),
+        Fit::Cover => image.resize_to_fill(w, h, FilterType::Lanczos3),
+        Fit::Crop => {
+            let (cw, ch) = (w.min(image.width()), h.min(image.height()));
+            image.crop_imm((image.width() - cw) / 2, (image.height() - ch) / 2, cw, ch)
+        }
+    };
+    encode(image, format)
+}
+
+// a gif of the first seconds of the animation at thumbnail size
+pub fn build_animated_thumbnail(data: &[u8]) -> Res<Vec<u8>> {
+    let frames = match animation(image::guess_format(data).map_err(warp_e)?, Cursor::new(data))? {
+        Some(frames) => frames,
+        None => Frames::new(Box::new(std::iter::once(Ok(Frame::new(load(data)?.to_rgba8()))))),
+    };
+    let mut buffer = Vec::new();
+    let mut encoder = GifEncoder::new_with_speed(&mut buffer, 10);
+    encoder.set_repeat(Repeat::Infinite).map_err(warp_e)?;
+    let mut elapsed = 0;
+    for frame in frames {
+        let frame = frame.map_err(warp_e)?;
+        let delay = frame.delay();
+        let small = DynamicImage::ImageRgba8(frame.into_buffer()).thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgba8();
+        encoder.encode_frame(Frame::from_parts(small, 0, 0, delay)).map_err(warp_e)?;
+        elapsed += milliseconds(delay);
+        if elapsed >= ANIMATED_THUMBNAIL_DURATION {
+            break;
+        }
+    }
+    drop(encoder);
+    Ok(buffer)
+}
+
+pub fn build_frame(data: &[u8], index: u32, format: RenditionFormat) -> Res<Vec<u8>> {
+    encode(load_frame(data, index)?, format)
+}
+
+fn encode(image: DynamicImage, format: RenditionFormat) -> Res<Vec<u8>> {
+    // jpeg has no alpha channel and the webp encoder only takes 8 bit pixels
+    let (image, format) = match format {
+        RenditionFormat::Png => (image, ImageFormat::Png),
+        RenditionFormat::Jpeg => (DynamicImage::ImageRgb8(image.to_rgb8()), ImageFormat::Jpeg),
+        RenditionFormat::WebP => (DynamicImage::ImageRgba8(image.to_rgba8()), ImageFormat::WebP),
+    };
+    let mut buffer = Vec::new();
+    image.write_to(&mut Cursor::new(&mut buffer), format).map_err(warp_e)?;
+    Ok(buffer)
+}
+
+pub fn build_rendition_from_file(origin_file_node: &mut FileNode, target_file_node: &mut FileNode, w: u32, h: u32, fit: Fit, format: RenditionFormat) -> Res<()> {
+    let mut data = Vec::new();
+    origin_file_node.read_all(&mut data)?;
+    let rendition_data = build_rendition(&data, w, h, fit, format)?;
+    target_file_node.write(&rendition_data)?;
+    Ok(())
+}
+
+pub fn build_animated_thumbnail_from_file(origin_file_node: &mut FileNode, target_file_node: &mut FileNode) -> Res<()> {
+    let mut data = Vec::new();
+    origin_file_node.read_all(&mut data)?;
+    let thumbnail_data = build_animated_thumbnail(&data)?;
+    target_file_node.write(&thumbnail_data)?;
+    Ok(())
+}
+
+pub fn build_frame_from_file(origin_file_node: &mut FileNode, target_file_node: &mut FileNode, index: u32, format: RenditionFormat) -> Res<()> {
+    let mut data = Vec::new();
+    origin_file_node.read_all(&mut data)?;
+    let frame_data = build_frame(&data, index, format)?;
+    target_file_node.write(&frame_data)?;
+    Ok(())
+}
+
 pub fn build_thumbnail_from_file(origin_file_node: &mut FileNode, target_file_node: &mut FileNode) -> Res<()> {
     let mut data = Vec::new();
     origin_file_node.read_all(&mut data)?;
@@ -28,3 +325,7 @@ pub fn build_thumbnail_from_file(origin_file_node: &mut FileNode, target_file_no
 fn warp_e(e: ImageError) -> Error {
     Error::ImageLoadError(e.to_string())
 }
+
+fn warp_tiff(e: TiffError) -> Error {
+    Error::ImageLoadError(e.to_string())
+}
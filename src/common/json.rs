@@ -1,4 +1,5 @@
 use serde::{de, Serialize};
+use serde_json::Value;
 use crate::common::{Error, Res};
 
 pub fn stringify<T> (v: &T) -> Res<String>
@@ -14,3 +15,31 @@ where
 {
     serde_json::from_str(s).map_err(|e| Error::ParseJsonError(e.to_string()))
 }
+
+// applies an RFC 7386 merge patch to the json form of v, a null in the patch removes the field
+pub fn patch<T>(v: &T, patch: &Value) -> Res<T>
+where
+    T: Serialize + de::DeserializeOwned,
+{
+    let mut value = serde_json::to_value(v).map_err(|_| Error::CastToJsonError)?;
+    merge(&mut value, patch);
+    serde_json::from_value(value).map_err(|e| Error::ParseJsonError(e.to_string()))
+}
+
+fn merge(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let target = target.as_object_mut().unwrap();
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
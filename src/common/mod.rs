@@ -1,10 +1,18 @@
+pub mod bktree;
+pub mod blurhash;
+pub mod color;
+pub mod exif;
 pub mod file;
+pub mod geo;
+pub mod hash;
 pub mod image;
 pub mod json;
+pub mod natural;
 pub mod result;
 
 pub use file::{Node, DirNode, FileNode,
                content_type::FileType, content_type::ContentType, content_type::file_check, content_type::from};
-pub use json::{stringify, parse};
+pub use exif::{read_exif, Exif};
+pub use hash::ContentHasher;
 pub use result::{Res, Error};
-pub use image::{build_thumbnail, build_thumbnail_from_file, get_size};
+pub use image::{build_animated_thumbnail_from_file, build_frame_from_file, build_rendition_from_file, build_thumbnail_from_file, can_decode, dominant_colors, get_size, load_file, perceptual_hash, placeholder, Fit, FrameInfo, RenditionFormat};
@@ -0,0 +1,36 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// names as people read them, "img2" before "img10" and "B" next to "b", the plain order only breaks ties
+pub fn compare(lhs: &str, rhs: &str) -> Ordering {
+    let (mut l, mut r) = (lhs.chars().peekable(), rhs.chars().peekable());
+    loop {
+        let order = match (l.peek(), r.peek()) {
+            (None, None) => return lhs.cmp(rhs),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) if a.is_ascii_digit() && b.is_ascii_digit() => {
+                let (a, b) = (digits(&mut l), digits(&mut r));
+                let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            }
+            (Some(&a), Some(&b)) => {
+                l.next();
+                r.next();
+                a.to_lowercase().cmp(b.to_lowercase())
+            }
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits
+}
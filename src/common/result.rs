@@ -1,11 +1,9 @@
-use std::any::Any;
 use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, HttpResponseBuilder};
 use serde::Serialize;
 use std::cell::BorrowMutError;
 use std::fmt::{Debug, Display, Formatter};
 use std::sync::PoisonError;
-use log::error;
 use serde::ser::StdError;
 
 pub enum Error {
@@ -22,6 +20,7 @@ pub enum Error {
     DirectoryError(String),
     SqliteError(rusqlite::Error),
     ConcurrentRequests,
+    TransactionError(String),
     TimestampError(i64),
     NoSuchFile,
 
@@ -32,6 +31,7 @@ pub enum Error {
     NoSuchRepo,
     UsedRepoName,
     SomeConfigCanNotChange,
+    UnsupportedRepoConfig,
 
     // endregion
 
@@ -50,6 +50,19 @@ pub enum Error {
     ItemNotFound,
     UnknownFileContentType,
     ImageLoadError(String),
+    ThumbnailNotSupported,
+    RenditionSizeNotAllowed(u32),
+    NoPerceptualHash,
+    NoSuchFrame(u32),
+    ItemNotInTrash,
+    BatchTooLarge(usize),
+    BatchFailed,
+    InvalidCursor,
+    DuplicateItem(i64),
+    UploadTooLarge(usize),
+    NoSuchUploadSession,
+    UploadOffsetMismatch(usize),
+    UploadSessionBusy,
 
     // endregion
 
@@ -76,6 +89,7 @@ impl Error {
             Error::ParseJsonError(str) => String::from(str),
             Error::DirectoryError(str) => String::from(str),
             Error::SqliteError(err) => String::from(err.to_string()),
+            Error::TransactionError(str) => String::from(str),
 
             Error::NeedLogin => String::from("you are not login"),
             Error::NoSuchUser => String::from("no such user"),
@@ -85,6 +99,19 @@ impl Error {
             Error::ItemNotFound => String::from("item not found"),
             Error::UnknownFileContentType => String::from("unknown file content-type"),
             Error::ImageLoadError(str) => String::from(str),
+            Error::ThumbnailNotSupported => String::from("no thumbnail for this content-type"),
+            Error::RenditionSizeNotAllowed(size) => format!("size {} is not allowed in this repo", size),
+            Error::NoPerceptualHash => String::from("item has no perceptual hash"),
+            Error::NoSuchFrame(index) => format!("item has no frame {}", index),
+            Error::ItemNotInTrash => String::from("item is not in the trash"),
+            Error::BatchTooLarge(size) => format!("a batch takes at most {} items", size),
+            Error::BatchFailed => String::from("some items of the batch failed, nothing is applied"),
+            Error::InvalidCursor => String::from("cursor does not belong to this list"),
+            Error::DuplicateItem(id) => format!("same content as item {}", id),
+            Error::UploadTooLarge(limit) => format!("upload is larger than {} bytes", limit),
+            Error::NoSuchUploadSession => String::from("no such upload session"),
+            Error::UploadOffsetMismatch(offset) => format!("upload session is at offset {}", offset),
+            Error::UploadSessionBusy => String::from("another request is working on this upload session"),
 
             Error::ConcurrentRequests => String::from("busy, please retry after a minute"),
             Error::TimestampError(timestamp) => format!("timestamp from {} failed", timestamp), 
@@ -92,6 +119,7 @@ impl Error {
             Error::NoSuchRepo => String::from("no such repo"),
             Error::UsedRepoName => String::from("used repo name"),
             Error::SomeConfigCanNotChange => String::from("some repo config can not be changed"),
+            Error::UnsupportedRepoConfig => String::from("repo config is not supported"),
             Error::PermissionCheckFailed => String::from("no permission"),
             Error::TagNotFound => String::from("tag not found"),
             Error::TagRelationNotFound => String::from("tag relation not found"),
@@ -111,6 +139,7 @@ impl Error {
             Error::ParseJsonError(_) |
             Error::DirectoryError(_) |
             Error::SqliteError(_) |
+            Error::TransactionError(_) |
             Error::TimestampError(_) |
             Error::ConcurrentRequests
             => StatusCode::BAD_GATEWAY,
@@ -131,10 +160,28 @@ impl Error {
             Error::UsedRepoName |
             Error::TagNotFound |
             Error::SomeConfigCanNotChange |
+            Error::UnsupportedRepoConfig |
             Error::TagRelationNotFound |
-            Error::ImageLoadError(_)
+            Error::ImageLoadError(_) |
+            Error::ThumbnailNotSupported |
+            Error::RenditionSizeNotAllowed(_) |
+            Error::NoPerceptualHash |
+            Error::NoSuchFrame(_) |
+            Error::ItemNotInTrash |
+            Error::BatchTooLarge(_) |
+            Error::BatchFailed |
+            Error::InvalidCursor |
+            Error::DuplicateItem(_) |
+            Error::NoSuchUploadSession
             => StatusCode::BAD_REQUEST,
 
+            Error::UploadOffsetMismatch(_) |
+            Error::UploadSessionBusy
+            => StatusCode::CONFLICT,
+
+            Error::UploadTooLarge(_)
+            => StatusCode::PAYLOAD_TOO_LARGE,
+
             // _ => panic!("{:?}", self)
         }
     }
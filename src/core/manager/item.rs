@@ -1,76 +1,171 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, Utc};
 pub use extend::{ImageExtend, ItemExtend, PhotoExtend, PictureExtend, RgbColor};
 
 use crate::common::{from, json, ContentType, Error, Res};
 use crate::core::manager::Setting;
-use crate::core::repository::{item, ItemStorage};
+use crate::core::repository::{GeoStorage, ItemStorage, ItemStore, SortQuery, Storage};
+use std::sync::Arc;
 
-pub struct ItemManager;
+// ids per statement when loading sort keys, well below the parameter limit of sqlite
+const NAME_CHUNK: usize = 1000;
+
+pub struct ItemManager {
+    store: Arc<dyn ItemStore>,
+}
 
 impl ItemManager {
-    pub fn new(_: &Setting) -> Self {
-        Self {}
+    pub fn new(_: &Setting, storage: &Storage) -> Self {
+        Self { store: storage.item.clone() }
     }
 
     pub fn create(&self, item: Item) -> Res<Item> {
         let mut temp = item.cast()?;
-        temp.id = item::create(&temp)?;
-        let temp = item::select_by_id(temp.id)?;
+        temp.id = self.store.create(&temp)?;
+        let temp = self.store.select_by_id(temp.id)?;
         Ok(Item::new(temp)?)
     }
 
     pub fn import(&self, item: Item) -> Res<Item> {
         let mut temp = item.cast()?;
-        temp.id = item::import(&temp)?;
+        temp.id = self.store.import(&temp)?;
         Ok(Item::new(temp)?)
     }
 
     pub fn delete(&self, id: i64) -> Res<()> {
-        item::mark_delete_item(id)
+        self.store.mark_delete(id)
+    }
+
+    pub fn restore(&self, id: i64) -> Res<()> {
+        self.store.reset(id)
+    }
+
+    pub fn purge(&self, id: i64) -> Res<()> {
+        self.store.purge(id)
+    }
+
+    pub fn select_deleted(&self, repo_id: i64, end_id: i64, limit: i64) -> Res<Vec<Item>> {
+        self.store.select_deleted(repo_id, end_id, limit)?.into_iter().map(Item::new).collect()
+    }
+
+    pub fn select_expired(&self, repo_id: i64, deleted_before: DateTime<Utc>, limit: i64) -> Res<Vec<Item>> {
+        self.store.select_expired(repo_id, deleted_before.timestamp(), limit)?.into_iter().map(Item::new).collect()
+    }
+
+    pub fn count_by_path(&self, repo_id: i64, path: &str) -> Res<usize> {
+        self.store.count_by_path(repo_id, path)
     }
 
     pub fn update(&self, item: Item) -> Res<Item> {
         let temp: ItemStorage = item.cast()?;
-        item::update_item(&temp)?;
+        self.store.update(&temp)?;
         Ok(Item::new(temp)?)
     }
 
     pub fn change_path(&self, id: i64, new_path: &str) -> Res<()> {
-        item::change_path(id, new_path)
+        self.store.change_path(id, new_path)
     }
 
     pub fn change_repo(&self, id: i64, new_repo_id: i64) -> Res<()> {
-        item::change_repo(id, new_repo_id)
+        self.store.change_repo(id, new_repo_id)
     }
 
     pub fn select_by_id(&self, id: i64) -> Res<Item> {
-        let item = item::select_by_id(id)?;
+        let item = self.store.select_by_id(id)?;
         Ok(Item::new(item)?)
     }
 
+    // an item in the trash is only reachable through the trash
+    pub fn select_live_by_id(&self, id: i64) -> Res<Item> {
+        let item = self.select_by_id(id)?;
+        if item.is_deleted {
+            return Err(Error::ItemNotFound);
+        }
+        Ok(item)
+    }
+
+    // items in the trash are left out
     pub fn select_by_ids(&self, ids: &Vec<i64>) -> Res<Vec<Item>> {
-        let item_list = item::select_item_by_ids(ids)?;
-        item_list.into_iter().map(|item| Item::new(item)).collect()
+        let item_list = self.store.select_by_ids(ids)?;
+        item_list.into_iter().map(Item::new).collect()
+    }
+
+    pub fn select_by_hash(&self, repo_id: i64, hash: &str) -> Res<Vec<Item>> {
+        self.store.select_by_hash(repo_id, hash)?.into_iter().map(Item::new).collect()
     }
 
     pub fn select_start_time(&self, repo_id: i64, start_time: i64) -> Res<i64> {
-        item::select_start_time(repo_id, start_time)
+        self.store.select_start_time(repo_id, start_time)
     }
 
     pub fn select_end_time(&self, repo_id: i64, end_time: i64) -> Res<i64> {
-        item::select_end_time(repo_id, end_time)
+        self.store.select_end_time(repo_id, end_time)
     }
     
     pub fn select_start_end_id(&self, repo_id: i64) -> Res<(i64, i64)> {
-        item::select_min_max_id(repo_id)
+        self.store.select_min_max_id(repo_id)
     }
 
     pub fn select_to(&self, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<Item>> {
-        item::select_to(repo_id, start_id, end_id, limit)?.into_iter().map(|item| Item::new(item)).collect()
+        self.store.select_to(repo_id, start_id, end_id, limit)?.into_iter().map(Item::new).collect()
     }
 
     pub fn select_from(&self, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<Item>> {
-        item::select_from(repo_id, start_id, end_id, limit)?.into_iter().map(|item| Item::new(item)).collect()
+        self.store.select_from(repo_id, start_id, end_id, limit)?.into_iter().map(Item::new).collect()
+    }
+
+    pub fn count_range(&self, repo_id: i64, start_id: i64, end_id: i64) -> Res<i64> {
+        self.store.count_range(repo_id, start_id, end_id)
+    }
+
+    pub fn search(&self, repo_id: i64, keyword: &str) -> Res<Vec<i64>> {
+        self.store.search(repo_id, keyword)
+    }
+
+    pub fn select_geo(&self, repo_id: i64, min_lat: f64, max_lat: f64, min_lng: f64, max_lng: f64) -> Res<Vec<GeoPoint>> {
+        Ok(self.store.select_geo(repo_id, min_lat, max_lat, min_lng, max_lng)?.into_iter().map(GeoPoint::new).collect())
+    }
+
+    // (item id, hash)
+    pub fn select_phash(&self, repo_id: i64) -> Res<Vec<(i64, u64)>> {
+        Ok(self.store.select_phash(repo_id)?.into_iter()
+            .filter_map(|phash| u64::from_str_radix(&phash.phash, 16).ok().map(|hash| (phash.item_id, hash)))
+            .collect())
+    }
+
+    // (item id, name)
+    pub fn select_names(&self, repo_id: i64, start_id: i64, end_id: i64) -> Res<Vec<(i64, String)>> {
+        Ok(self.store.select_names(repo_id, start_id, end_id)?.into_iter().map(|name| (name.item_id, name.name)).collect())
+    }
+
+    // in chunks, a condition may leave more ids than a statement takes parameters
+    pub fn select_names_by_ids(&self, ids: &[i64]) -> Res<Vec<(i64, String)>> {
+        let mut names = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(NAME_CHUNK) {
+            names.extend(self.store.select_names_by_ids(chunk)?.into_iter().map(|name| (name.item_id, name.name)));
+        }
+        Ok(names)
+    }
+
+    // (item id, the value it is ordered by)
+    pub fn select_sorted(&self, query: &SortQuery<'_>, limit: i64) -> Res<Vec<(i64, Option<f64>)>> {
+        Ok(self.store.select_sorted(query, limit)?.into_iter().map(|sorted| (sorted.item_id, sorted.value)).collect())
+    }
+
+    pub fn count_sorted(&self, query: &SortQuery<'_>) -> Res<i64> {
+        self.store.count_sorted(query)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct GeoPoint {
+    pub item_id: i64,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl GeoPoint {
+    fn new(geo: GeoStorage) -> Self {
+        Self { item_id: geo.item_id, latitude: geo.latitude, longitude: geo.longitude }
     }
 }
 
@@ -87,11 +182,19 @@ pub struct Item {
     pub repo_id: i64,
     pub path: String,
     pub extend: ItemExtend,
+    pub caption: Option<String>,
+    pub hash: Option<String>,
+    #[serde(default, with = "chrono::serde::ts_milliseconds_option")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl Item {
     fn new(item: ItemStorage) -> Res<Self> {
         let created_at = DateTime::from_timestamp(item.created_at, 0).ok_or(Error::TimestampError(item.created_at))?;
+        let deleted_at = match item.deleted_at {
+            Some(deleted_at) => Some(DateTime::from_timestamp(deleted_at, 0).ok_or(Error::TimestampError(deleted_at))?),
+            None => None,
+        };
         Ok(Self {
             id: item.id,
             name: item.name,
@@ -102,6 +205,9 @@ impl Item {
             repo_id: item.repo_id,
             path: item.path,
             extend: json::parse(&item.extend)?,
+            caption: item.caption,
+            hash: item.hash,
+            deleted_at,
         })
     }
 
@@ -116,11 +222,15 @@ impl Item {
             repo_id: self.repo_id,
             path: self.path,
             extend: json::stringify(&self.extend)?,
+            caption: self.caption,
+            hash: self.hash,
+            deleted_at: self.deleted_at.map(|deleted_at| deleted_at.timestamp()),
         })
     }
 }
 
 pub mod extend {
+    use crate::common::Exif;
     use std::ops::Deref;
 
     #[derive(serde::Serialize, serde::Deserialize)]
@@ -133,7 +243,7 @@ pub mod extend {
         Photo(PhotoExtend),
     }
 
-    #[derive(serde::Serialize, serde::Deserialize)]
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
     pub struct RgbColor {
         pub r: u8,
         pub g: u8,
@@ -145,6 +255,27 @@ pub mod extend {
     pub struct ImageExtend {
         pub w: u32,
         pub h: u32,
+        // dominant colors, largest first
+        #[serde(default)]
+        pub palette: Vec<RgbColor>,
+        // 64 bit dHash as 16 hex digits
+        #[serde(default)]
+        pub phash: Option<String>,
+        // blurhash placeholder for the tile while the thumbnail loads
+        #[serde(default)]
+        pub blurhash: Option<String>,
+        // frames of an animation or pages of a tiff, 1 for a still image
+        #[serde(default = "default_frames")]
+        pub frames: u32,
+        // one loop of the animation in milliseconds
+        #[serde(default)]
+        pub duration: u64,
+        #[serde(default)]
+        pub animated: bool,
+    }
+
+    fn default_frames() -> u32 {
+        1
     }
 
     #[derive(serde::Serialize, serde::Deserialize)]
@@ -157,7 +288,8 @@ pub mod extend {
     #[derive(serde::Serialize, serde::Deserialize)]
     pub struct PhotoExtend {
         pub image_extend: ImageExtend,
-        // prepare for EXIF info
+        #[serde(default)]
+        pub exif: Option<Exif>,
     }
     
     impl Deref for PictureExtend {
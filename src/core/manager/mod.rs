@@ -6,25 +6,45 @@ pub(in crate::core) mod tag;
 
 pub(in crate::core) use item::ItemManager;
 pub(in crate::core) use repo::RepoManager;
-pub(in crate::core) use resource::ResourceManager;
+pub(in crate::core) use resource::{RepoResourceManager, ResourceManager};
 pub(in crate::core) use user::UserManager;
 
-pub use item::{ImageExtend, Item, ItemExtend, PhotoExtend, PictureExtend};
-pub use repo::{CommonConfig, Repo, RepoConfig, RepoFileOrder, IllustrationConfig};
+pub use item::{ImageExtend, Item, ItemExtend, PhotoExtend, PictureExtend, RgbColor};
+pub use repo::{CommonConfig, DuplicateMode, Repo, RepoConfig, RepoFileOrder};
 pub use tag::{Tag, MarkedTag};
 pub use user::{User, UserRole};
 
-use crate::common::DirNode;
+use crate::common::{DirNode, Node, Res};
 use crate::core::manager::tag::TagManager;
+use crate::core::repository::{Database, Storage, UnitOfWork};
 use std::sync::Arc;
+use std::time::Duration;
 
 pub struct Setting {
     pub root: DirNode,
     pub max_thumbnail_size: usize,
+    pub database: DatabaseSetting,
+}
+
+pub struct DatabaseSetting {
+    // default to `data.db` under the root
+    pub path: Option<String>,
+    pub pool_size: u32,
+    pub busy_timeout: Duration,
+    pub wal: bool,
+    pub synchronous: Synchronous,
+}
+
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
 }
 
 pub struct Config {
     pub(in crate::core) setting: Setting,
+    pub(in crate::core) unit: Arc<dyn UnitOfWork>,
     pub(in crate::core) repo_manager: Arc<RepoManager>,
     pub(in crate::core) user_manager: Arc<UserManager>,
     pub(in crate::core) resource_manager: Arc<ResourceManager>,
@@ -33,16 +53,29 @@ pub struct Config {
 }
 
 impl Config {
-    pub fn new(root: DirNode, max_thumbnail_size: usize) -> Self {
-        let setting = Setting { root, max_thumbnail_size };
-        let repo = Arc::new(RepoManager::new(&setting));
-        let user = Arc::new(UserManager::new(&setting));
+    pub fn new(root: DirNode, max_thumbnail_size: usize, database: DatabaseSetting) -> Res<Self> {
+        let setting = Setting { root, max_thumbnail_size, database };
+        let storage = Storage::sqlite(Self::open_database(&setting)?);
+        Ok(Self::with_storage(setting, storage))
+    }
+
+    // keep nothing on disk except the resource files, for tests
+    #[cfg(test)]
+    pub fn in_memory(root: DirNode, max_thumbnail_size: usize) -> Self {
+        let setting = Setting { root, max_thumbnail_size, database: DatabaseSetting::default() };
+        Self::with_storage(setting, Storage::memory())
+    }
+
+    fn with_storage(setting: Setting, storage: Storage) -> Self {
+        let repo = Arc::new(RepoManager::new(&setting, &storage));
+        let user = Arc::new(UserManager::new(&setting, &storage));
         let resource = Arc::new(ResourceManager::new(&setting));
-        let item = Arc::new(ItemManager::new(&setting));
-        let tag = Arc::new(TagManager::new(&setting));
+        let item = Arc::new(ItemManager::new(&setting, &storage));
+        let tag = Arc::new(TagManager::new(&setting, &storage));
 
         Config {
             setting,
+            unit: storage.unit,
             repo_manager: repo,
             user_manager: user,
             resource_manager: resource,
@@ -50,4 +83,37 @@ impl Config {
             tag_manager: tag,
         }
     }
+
+    fn open_database(setting: &Setting) -> Res<Database> {
+        let database = &setting.database;
+        let path = match &database.path {
+            Some(path) => path.clone(),
+            None => setting.root.next(String::from("data.db")).absolute_path(),
+        };
+        setting.root.mkdir()?;
+        Database::open(&path, database.pool_size, database.busy_timeout, database.wal, database.synchronous.as_pragma())
+    }
+}
+
+impl Default for DatabaseSetting {
+    fn default() -> Self {
+        Self {
+            path: None,
+            pool_size: 8,
+            busy_timeout: Duration::from_secs(5),
+            wal: true,
+            synchronous: Synchronous::Normal,
+        }
+    }
+}
+
+impl Synchronous {
+    fn as_pragma(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
 }
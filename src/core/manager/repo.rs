@@ -1,20 +1,22 @@
-pub use config::{CommonConfig, RepoConfig, RepoFileOrder, IllustrationConfig};
+pub use config::{CommonConfig, DuplicateMode, RepoConfig, RepoFileOrder};
 
 use crate::common::{json, Error, Res};
 use crate::core::manager::Setting;
-use crate::core::repository::repo;
-use crate::core::repository::repo::RepoStorage;
+use crate::core::repository::{RepoStorage, RepoStore, Storage};
+use std::sync::Arc;
 
-pub struct RepoManager;
+pub struct RepoManager {
+    store: Arc<dyn RepoStore>,
+}
 
 impl RepoManager {
-    pub fn new(_: &Setting) -> Self {
-        Self {}
+    pub fn new(_: &Setting, storage: &Storage) -> Self {
+        Self { store: storage.repo.clone() }
     }
 
     pub fn list_repo(&self) -> Res<Vec<Repo>> {
-        Ok(repo::select_all_repo()?.into_iter()
-            .map(|repo| Repo::new(repo))
+        Ok(self.store.select_all_repo()?.into_iter()
+            .map(Repo::new)
             .filter_map(|repo| repo.ok())
             .collect())
     }
@@ -22,25 +24,25 @@ impl RepoManager {
     pub fn create_repo(&self, name: String, config: RepoConfig) -> Res<Repo> {
         let repo_vo = Repo { id: 0, name, config };
         let mut repo = repo_vo.cast()?;
-        repo.id = repo::create_repo(&repo)?;
+        repo.id = self.store.create_repo(&repo)?;
         Ok(Repo::new(repo)?)
     }
 
     pub fn delete_repo(&self, id: i64) -> Res<()> {
-        repo::delete_repo(id)
+        self.store.delete_repo(id)
     }
 
     pub fn get_repo_list(&self, id: &Vec<i64>) -> Res<Vec<Repo>> {
-        let repo_vec = repo::select_all_repo()?;
+        let repo_vec = self.store.select_all_repo()?;
         Ok(repo_vec.into_iter()
             .filter(|repo| id.contains(&repo.id))
-            .map(|repo| Repo::new(repo))
+            .map(Repo::new)
             .filter_map(|repo| repo.ok())
             .collect())
     }
 
     pub fn select_repo_by_name(&self, name: &str) -> Res<Repo> {
-        let repo_vec = repo::select_all_repo()?;
+        let repo_vec = self.store.select_all_repo()?;
         match repo_vec.into_iter().find(|repo| repo.name == name) {
             None => Err(Error::NoSuchRepo),
             Some(repo) => Ok(Repo::new(repo)?),
@@ -48,7 +50,7 @@ impl RepoManager {
     }
 
     pub fn select_repo_by_id(&self, id: i64) -> Res<Repo> {
-        let repo_vec = repo::select_all_repo()?;
+        let repo_vec = self.store.select_all_repo()?;
         match repo_vec.into_iter().find(|repo| repo.id == id) {
             None => Err(Error::NoSuchRepo),
             Some(repo) => Ok(Repo::new(repo)?),
@@ -56,7 +58,7 @@ impl RepoManager {
     }
 
     pub fn update_repo(&self, repo: &Repo) -> Res<()> {
-        repo::update_repo(repo.id, &repo.name, &json::stringify(&repo.config)?)
+        self.store.update_repo(repo.id, &repo.name, &json::stringify(&repo.config)?)
     }
 }
 
@@ -93,6 +95,7 @@ pub mod config {
     #[derive(serde::Serialize, serde::Deserialize)]
     pub enum RepoConfig {
         Illustration(IllustrationConfig),
+        Photo(PhotoConfig),
         UnSupportConfig,
     }
 
@@ -104,9 +107,43 @@ pub mod config {
         // Gallery,
     }
 
+    // what an upload does when the repo already holds the same bytes
+    #[derive(serde::Serialize, serde::Deserialize, Default)]
+    pub enum DuplicateMode {
+        #[default]
+        Allow,
+        ReturnExisting,
+        Reject,
+        // new item sharing the file of the existing one
+        Link,
+    }
+
     #[derive(serde::Serialize, serde::Deserialize)]
     pub struct CommonConfig {
         pub order: RepoFileOrder,
+        #[serde(default)]
+        pub duplicate: DuplicateMode,
+        // in bytes
+        #[serde(default = "default_max_upload_size")]
+        pub max_upload_size: usize,
+        // the widths and heights a rendition may be asked for, each one is cached on disk
+        #[serde(default = "default_rendition_sizes")]
+        pub rendition_sizes: Vec<u32>,
+        // days an item stays in the trash before the purge removes it with its file
+        #[serde(default = "default_trash_retention_days")]
+        pub trash_retention_days: u32,
+    }
+
+    fn default_max_upload_size() -> usize {
+        100 * 1024 * 1024
+    }
+
+    fn default_rendition_sizes() -> Vec<u32> {
+        vec![64, 128, 256, 512, 768, 1024, 1536, 2048]
+    }
+
+    fn default_trash_retention_days() -> u32 {
+        30
     }
 
     #[derive(serde::Serialize, serde::Deserialize)]
@@ -114,13 +151,27 @@ pub mod config {
         pub common_config: CommonConfig,
     }
 
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct PhotoConfig {
+        pub common_config: CommonConfig,
+    }
+
     impl RepoConfig {
         pub fn update_check(old: &RepoConfig, cur: &RepoConfig) -> Res<()> {
             match (old, cur) {
                 (RepoConfig::Illustration(old_conf), RepoConfig::Illustration(new_conf)) => { IllustrationConfig::update_check(old_conf, new_conf) }
+                (RepoConfig::Photo(old_conf), RepoConfig::Photo(new_conf)) => { PhotoConfig::update_check(old_conf, new_conf) }
                 (_, _) => Err(Error::SomeConfigCanNotChange),
             }
         }
+
+        pub fn common_config(&self) -> Res<&CommonConfig> {
+            match self {
+                RepoConfig::Illustration(config) => Ok(config),
+                RepoConfig::Photo(config) => Ok(config),
+                RepoConfig::UnSupportConfig => Err(Error::UnsupportedRepoConfig),
+            }
+        }
     }
 
     impl CommonConfig {
@@ -139,6 +190,12 @@ pub mod config {
         }
     }
 
+    impl PhotoConfig {
+        fn update_check(old_conf: &PhotoConfig, new_conf: &PhotoConfig) -> Res<()> {
+            CommonConfig::update_check(old_conf, new_conf)
+        }
+    }
+
     impl Deref for IllustrationConfig {
         type Target = CommonConfig;
 
@@ -146,4 +203,12 @@ pub mod config {
             &self.common_config
         }
     }
+
+    impl Deref for PhotoConfig {
+        type Target = CommonConfig;
+
+        fn deref(&self) -> &Self::Target {
+            &self.common_config
+        }
+    }
 }
@@ -1,9 +1,9 @@
 use crate::common::file::content_type;
-use crate::common::Error::{NickOrPasswordError, NoSuchFile};
 use crate::common::{ContentType, DirNode, FileNode, Node, Res};
 use crate::core::manager::Setting;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use uuid::Uuid;
 
 pub struct ResourceManager {
@@ -60,18 +60,36 @@ impl RepoResourceManager {
         Ok(Self { home, cache, temp })
     }
 
-    pub fn create_temp_file(&self, data: &Vec<u8>) -> Res<FileNode> {
+    pub fn create_temp_file(&self) -> Res<FileNode> {
         let file_name = Uuid::new_v4().to_string();
-        let mut file_node = self.temp.to(file_name, &content_type::UNKNOWN);
+        let file_node = self.temp.to(file_name, &content_type::UNKNOWN);
         file_node.touch()?;
-        file_node.write(data)?;
         Ok(file_node)
     }
 
-    pub fn write_file(&self, data: &Vec<u8>, mut file: FileNode) -> Res<FileNode> {
+    pub fn temp_file(&self, name: &str, content_type: &'static ContentType) -> FileNode {
+        self.temp.to(String::from(name), content_type)
+    }
+
+    // an upload session is <id> with its meta <id>.json, the meta is rewritten on every chunk,
+    // other files in .temp belong to uploads still streaming and are left alone
+    pub fn idle_upload_sessions(&self, ttl: Duration) -> Res<Vec<String>> {
+        let mut ids = Vec::new();
+        for file in self.temp.list_files()? {
+            let name = file.name();
+            let Some(id) = name.strip_suffix(content_type::JSON.ext) else {
+                continue;
+            };
+            if Uuid::parse_str(id).is_ok() && file.modified()?.elapsed().unwrap_or_default() > ttl {
+                ids.push(String::from(id));
+            }
+        }
+        Ok(ids)
+    }
+
+    pub fn move_temp_file(&self, temp: &FileNode, mut file: FileNode) -> Res<FileNode> {
         file.set_root_parent(&self.home);
-        file.touch()?;
-        file.write(data)?;
+        temp.move_to_file(&file)?;
         Ok(file)
     }
 
@@ -83,7 +101,62 @@ impl RepoResourceManager {
 
     pub fn build_thumbnail_file(&self, path: &str) -> FileNode {
         let mut file_node = <FileNode as Node>::from(path);
-        file_node.set_content_type(&content_type::JPEG);
+        file_node.set_content_type(&content_type::PNG);
+        file_node.set_root_parent(&self.cache);
+        file_node
+    }
+
+    // drops the thumbnail and every rendition of the file, they are built again on the next read
+    pub fn clear_cache(&self, path: &str) -> Res<()> {
+        for file in self.cache_files(path)? {
+            file.remove()?;
+        }
+        Ok(())
+    }
+
+    // the cache follows the file to new_path in another repo, keep leaves a copy for a file still used here
+    pub fn move_cache(&self, path: &str, target: &RepoResourceManager, new_path: &str, keep: bool) -> Res<()> {
+        let stem = Self::cache_stem(&self.build_thumbnail_file(path));
+        let new_thumbnail = target.build_thumbnail_file(new_path);
+        let new_stem = Self::cache_stem(&new_thumbnail);
+        let new_dir = new_thumbnail.up().unwrap();
+        for file in self.cache_files(path)? {
+            let name = Self::cache_stem(&file);
+            let new_file = new_dir.to(format!("{}{}", new_stem, &name[stem.len()..]), file.content_type());
+            if keep {
+                file.copy_to_file(&new_file)?;
+            } else {
+                file.move_to_file(&new_file)?;
+            }
+        }
+        Ok(())
+    }
+
+    // the thumbnail and every rendition of the file
+    fn cache_files(&self, path: &str) -> Res<Vec<FileNode>> {
+        let thumbnail = self.build_thumbnail_file(path);
+        let dir = thumbnail.up().unwrap();
+        if !dir.is_exist() {
+            return Ok(Vec::new());
+        }
+        let stem = Self::cache_stem(&thumbnail);
+        Ok(dir.list_files()?.into_iter()
+            .filter(|file| {
+                let name = Self::cache_stem(file);
+                name == stem || name.starts_with(&format!("{}-", stem))
+            })
+            .collect())
+    }
+
+    fn cache_stem(file: &FileNode) -> String {
+        file.name().trim_end_matches(file.content_type().ext).to_string()
+    }
+
+    // key tells the renditions of one file apart, e.g. 512x512-Cover
+    pub fn build_rendition_file(&self, path: &str, key: &str, content_type: &'static ContentType) -> FileNode {
+        let stem = path.rsplit_once('.').map_or(path, |(stem, _)| stem);
+        let mut file_node = <FileNode as Node>::from(&format!("{}-{}", stem, key));
+        file_node.set_content_type(content_type);
         file_node.set_root_parent(&self.cache);
         file_node
     }
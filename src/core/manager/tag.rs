@@ -2,63 +2,104 @@ use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 use crate::common::{Error, Res};
 use crate::core::manager::Setting;
-use crate::core::repository::{item_tag_relation, tag, ItemTagRelation, TagStorage};
+use crate::core::repository::{ItemTagRelation, Storage, TagStorage, TagStore};
+use std::sync::Arc;
 
-pub struct TagManager;
+pub struct TagManager {
+    store: Arc<dyn TagStore>,
+}
 
 impl TagManager {
-    pub fn new(_: &Setting) -> Self { Self {} }
+    pub fn new(_: &Setting, storage: &Storage) -> Self {
+        Self { store: storage.tag.clone() }
+    }
 
     pub fn create(&self, name: String, creator: i64, repo_id: i64, parent: i64) -> Res<Tag> {
         let mut tmp = TagStorage { id: 0, name, repo_id, parent, creator, is_delete: false };
-        tmp.id = tag::create_tag(&tmp)?;
+        tmp.id = self.store.create_tag(&tmp)?;
         Tag::new(tmp)
     }
 
     pub fn select_by_id(&self, id: i64) -> Res<Tag> {
-        let tag = tag::select_by_id(id)?;
+        let tag = self.store.select_by_id(id)?;
+        Tag::new(tag)
+    }
+
+    pub fn select_deleted_by_id(&self, id: i64) -> Res<Tag> {
+        let tag = self.store.select_by_ids(&vec![id])?.into_iter().find(|tag| tag.is_delete).ok_or(Error::TagNotFound)?;
         Tag::new(tag)
     }
 
     pub fn select_all(&self, repo_id: i64) -> Res<Vec<Tag>> {
-        tag::select_all(repo_id)?.into_iter().map(|t| Tag::new(t)).collect()
+        self.store.select_all(repo_id)?.into_iter().map(Tag::new).collect()
     }
 
     pub fn update(&self, tag: Tag) -> Res<Tag> {
         let tmp = tag.cast()?;
-        tag::update_tag(&tmp)?;
+        self.store.update_tag(&tmp)?;
         Tag::new(tmp)
     }
 
+    // the tag and its relations change together, the caller runs it in a transaction
     pub fn delete(&self, tag_id: i64) -> Res<usize> {
-        tag::delete_tag(tag_id)?;
-        item_tag_relation::delete_tag(tag_id)
+        self.store.delete_tag(tag_id)?;
+        self.store.delete_relation_by_tag(tag_id)
     }
 
     pub fn delete_all(&self, item_id: i64) -> Res<usize> {
-        item_tag_relation::delete_item(item_id)
+        self.store.delete_relation_by_item(item_id)
+    }
+
+    pub fn purge_all(&self, item_id: i64) -> Res<usize> {
+        self.store.purge_relation_by_item(item_id)
+    }
+
+    // tags belong to one repo, each relation of the item moves to the tag of the same name in the target repo,
+    // or is dropped when there is none
+    pub fn move_item(&self, item_id: i64, repo_id: i64) -> Res<usize> {
+        let targets: HashMap<String, i64> = self.store.select_all(repo_id)?.into_iter().map(|tag| (tag.name, tag.id)).collect();
+        let relations: Vec<ItemTagRelation> = self.store.select_relation_by_item(item_id)?.into_iter().filter(|relation| !relation.is_delete).collect();
+        let tag_id_list = relations.iter().map(|relation| relation.tag_id).collect();
+        let names: HashMap<i64, String> = self.store.select_by_ids(&tag_id_list)?.into_iter().map(|tag| (tag.id, tag.name)).collect();
+        let mut moved = HashSet::new();
+        for relation in relations {
+            self.store.delete_relation(relation.id)?;
+            if let Some(tag_id) = names.get(&relation.tag_id).and_then(|name| targets.get(name)) {
+                if moved.insert(*tag_id) {
+                    self.apply_tag(*tag_id, item_id, relation.creator)?;
+                }
+            }
+        }
+        Ok(moved.len())
     }
 
     pub fn reset(&self, id: i64) -> Res<usize> {
-        tag::rest_tag(id)?;
-        item_tag_relation::revert_all(id)
+        self.store.reset_tag(id)?;
+        self.store.revert_relation_by_tag(id)
     }
 
+    // a tag that is already on the item stays as it is, with its creator
     pub fn apply_tag(&self, tag_id: i64, item_id: i64, creator: i64) -> Res<()> {
-        let relation = ItemTagRelation { id: 0, tag_id, item_id, creator, is_delete: false };
-        item_tag_relation::create(&relation)?;
-        Ok(())
+        match self.store.select_relation_by_both(item_id, tag_id) {
+            Ok(_) => Ok(()),
+            Err(Error::TagRelationNotFound) => {
+                let relation = ItemTagRelation { id: 0, tag_id, item_id, creator, is_delete: false };
+                self.store.create_relation(&relation)?;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
     }
 
     pub fn remove_tag(&self, tag_id: i64, item_id: i64) -> Res<()> {
-        let relation = item_tag_relation::select_by_both(tag_id, item_id)?;
-        item_tag_relation::delete(relation.id)
+        let relation = self.store.select_relation_by_both(item_id, tag_id)?;
+        self.store.delete_relation(relation.id)
     }
 
     pub fn select_item_tag(&self, item_id: i64) -> Res<Vec<MarkedTag>> {
-        let relation_list = item_tag_relation::select_by_item(item_id)?;
+        let relation_list = self.store.select_relation_by_item(item_id)?;
         let tag_id_list = relation_list.iter().map(|relation| relation.tag_id).collect();
-        let tag_list: Vec<Tag> = tag::select_by_ids(&tag_id_list)?.into_iter().map(|tag| Tag::new(tag)).collect::<Res<Vec<_>>>()?;
+        let tag_list: Vec<Tag> = self.store.select_by_ids(&tag_id_list)?.into_iter().map(Tag::new).collect::<Res<Vec<_>>>()?;
         let mut tag_map: HashMap<i64, Tag> = tag_list.into_iter().map(|tag| (tag.id, tag)).collect();
         relation_list.into_iter().map(|relation| {
             let tag = tag_map.remove(&relation.tag_id).ok_or(Error::TagNotFound)?;
@@ -67,7 +108,7 @@ impl TagManager {
     }
 
     pub fn select_items_tag(&self, items: &Vec<i64>) -> Res<HashMap<i64, HashSet<i64>>> {
-        let relation_list = item_tag_relation::select_by_items(items)?;
+        let relation_list = self.store.select_relation_by_items(items)?;
         let mut result = HashMap::new();
         for relation in relation_list {
             let option = result.get_mut(&relation.item_id);
@@ -84,17 +125,17 @@ impl TagManager {
     }
 
     pub fn select_marked_tag(&self, tag_id: i64, item_id: i64) -> Res<MarkedTag> {
-        let relation = item_tag_relation::select_by_both(tag_id, item_id)?;
-        let tag = tag::select_by_id(relation.tag_id)?;
+        let relation = self.store.select_relation_by_both(item_id, tag_id)?;
+        let tag = self.store.select_by_id(relation.tag_id)?;
         Ok(MarkedTag::new(relation, Tag::new(tag)?))
     }
 
     pub fn remove_all_tag(&self, tag_id: i64) -> Res<usize> {
-        item_tag_relation::delete_tag(tag_id)
+        self.store.delete_relation_by_tag(tag_id)
     }
     
     pub fn select_item_by_tags(&self, tags: &Vec<i64>) -> Res<Vec<i64>> {
-        let relations = item_tag_relation::select_by_tags(tags)?;
+        let relations = self.store.select_relation_by_tags(tags)?;
         Ok(relations.into_iter().map(|relation| relation.item_id).collect())
     }
 }
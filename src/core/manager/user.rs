@@ -1,71 +1,74 @@
 use crate::common::{Error, Res};
 use crate::core::manager::Setting;
-use crate::core::repository::{user, user_repo_role, UserStorage, UserRepoRoleStorage};
+use crate::core::repository::{Storage, UserRepoRoleStorage, UserStorage, UserStore};
+use std::sync::Arc;
 use rand::Rng;
 use std::collections::HashMap;
 use std::ops::Add;
 
-pub struct UserManager;
+pub struct UserManager {
+    store: Arc<dyn UserStore>,
+}
 
 impl UserManager {
 
-    pub fn new(_: &Setting) -> Self {
-        Self {}
+    pub fn new(_: &Setting, storage: &Storage) -> Self {
+        Self { store: storage.user.clone() }
     }
     
     pub fn create_user(&self, name: String, password: &str) -> Res<User> {
-        if let Ok(_) = user::query_by_name(&name) {
+        if let Ok(_) = self.store.query_by_name(&name) {
             return Err(Error::UsedNick);
         }
         let mut user = UserStorage { id: 0, name, password: Self::encode_password(password), token: None };
-        user.id = user::create_user(&user)?;
+        user.id = self.store.create_user(&user)?;
         Ok(User::new(user, &Vec::new()))
     }
 
     pub fn check_password(&self, name: &str, password: &str) -> Res<User> {
-        let user = user::query_by_name(name)?;
+        let user = self.store.query_by_name(name)?;
         Self::password_check(password, &user.password)?;
-        let role_vec = user_repo_role::select_user_role(user.id)?;
+        let role_vec = self.store.select_user_role(user.id)?;
         Ok(User::new(user, &role_vec))
     }
     
     pub fn check_password_by_id(&self, id: i64, password: &str) -> Res<()> {
-        let user = user::query_by_id(id)?;
+        let user = self.store.query_by_id(id)?;
         Self::password_check(password, &user.password)?;
         Ok(())
     }
 
     pub fn check_token(&self, token: &str) -> Res<User> {
-        let user = user::query_by_token(token)?;
-        let role_vec = user_repo_role::select_user_role(user.id)?;
+        let user = self.store.query_by_token(token)?;
+        let role_vec = self.store.select_user_role(user.id)?;
         Ok(User::new(user, &role_vec))
     }
 
     pub fn clear_token(&self, id: i64) -> Res<()> {
-        user::clear_token(id)
+        self.store.clear_token(id)
     }
 
     pub fn update_token(&self, id: i64, token: &str) -> Res<()> {
-        user::update_token(id, token)
+        self.store.update_token(id, token)
     }
 
     pub fn update_password(&self, id: i64, password: &str) -> Res<()> {
-        user::update_password(id, &Self::encode_password(password))
+        self.store.update_password(id, &Self::encode_password(password))
     }
 
     pub fn update_user_role(&self, user_id: i64, repo_id: i64, role: UserRole) -> Res<()> {
-        let role_vec = user_repo_role::select_user_role(user_id)?;
+        let role_vec = self.store.select_user_role(user_id)?;
         let match_role: Vec<UserRepoRoleStorage> = role_vec.into_iter().filter(|role| role.repo_id == repo_id).collect();
         if match_role.is_empty() {
             let role = UserRepoRoleStorage { id: 0, user_id, repo_id, role: role.to_int() };
-            user_repo_role::create_user_repo_role(&role).map(|_| ())
+            self.store.create_user_repo_role(&role).map(|_| ())
         } else {
-            user_repo_role::update_user_role(match_role.get(0).unwrap().id, role.to_int())
+            self.store.update_user_role(match_role.get(0).unwrap().id, role.to_int())
         }
     }
 
     pub fn list_user_repo(&self, user_id: i64) -> Res<Vec<i64>> {
-        user_repo_role::select_user_role(user_id).map(|vec| vec.iter().map(|r| r.id).collect())
+        self.store.select_user_role(user_id).map(|vec| vec.iter().map(|r| r.id).collect())
     }
 
     fn password_check(input: &str, db_value: &str) -> Res<()> {
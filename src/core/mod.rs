@@ -2,4 +2,4 @@ mod manager;
 mod repository;
 pub mod service;
 
-pub use manager::Config;
\ No newline at end of file
+pub use manager::{Config, DatabaseSetting, Synchronous};
\ No newline at end of file
@@ -1,10 +1,16 @@
 use crate::common::{Error, Res};
 use chrono::NaiveDateTime;
-use once_cell::sync::Lazy;
+use crate::core::repository::{item, migration};
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::functions::FunctionFlags;
 use rusqlite::types::FromSql;
-use rusqlite::{Params, Row, ToSql};
+use rusqlite::{Connection, Params, Row, ToSql};
+use std::cell::RefCell;
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 pub(super) fn cast<E>(res: Res<E>) -> rusqlite::Result<E> {
     res.map_err(|e| {
@@ -21,14 +27,23 @@ pub(super) struct RowData<'tmp> {
 
 impl RowData<'_> {
     pub(super) fn get<T: FromSql>(&self, idx: usize) -> Res<T> {
-        self.row.get(idx).map_err(|e| Error::SqliteError(e))
+        self.row.get(idx).map_err(Error::SqliteError)
     }
 
     pub(super) fn get_timestamp(&self, idx: usize) -> Res<i64> {
-        let datetime_str: String = self.row.get(idx).map_err(|e| Error::SqliteError(e))?;
-        let naive_datetime = NaiveDateTime::parse_from_str(&datetime_str, "%Y-%m-%d %H:%M:%S").map_err(|e| Error::CastDbValueError(e.to_string()))?;
-        Ok(naive_datetime.and_utc().timestamp())
+        let datetime_str: String = self.row.get(idx).map_err(Error::SqliteError)?;
+        parse_timestamp(&datetime_str)
     }
+
+    pub(super) fn get_optional_timestamp(&self, idx: usize) -> Res<Option<i64>> {
+        let datetime_str: Option<String> = self.row.get(idx).map_err(Error::SqliteError)?;
+        datetime_str.map(|datetime_str| parse_timestamp(&datetime_str)).transpose()
+    }
+}
+
+fn parse_timestamp(datetime_str: &str) -> Res<i64> {
+    let naive_datetime = NaiveDateTime::parse_from_str(datetime_str, "%Y-%m-%d %H:%M:%S").map_err(|e| Error::CastDbValueError(e.to_string()))?;
+    Ok(naive_datetime.and_utc().timestamp())
 }
 
 pub(super) fn cast_placeholder<T>(vec: &Vec<T>) -> String {
@@ -39,19 +54,151 @@ pub(super) fn cast_list<T: ToSql>(vec: &Vec<T>) -> Vec<&dyn ToSql> {
     vec.iter().map(|x| x as &dyn ToSql).collect::<Vec<&dyn ToSql>>()
 }
 
-pub(super) const POOL: Lazy<Pool<SqliteConnectionManager>> = Lazy::new(|| {
-    let manager = SqliteConnectionManager::file("test/data.db");
-    Pool::new(manager).unwrap()
-});
+pub struct Database {
+    id: usize,
+    pool: Pool<SqliteConnectionManager>,
+}
+
+// the transaction opened by the current thread, every statement of the same database goes through it
+struct ActiveTransaction {
+    database: usize,
+    connection: Rc<PooledConnection<SqliteConnectionManager>>,
+    depth: usize,
+    rollback_only: bool,
+}
+
+pub struct Transaction {
+    database: usize,
+    finished: bool,
+}
+
+pub(super) enum Con {
+    Pooled(PooledConnection<SqliteConnectionManager>),
+    Shared(Rc<PooledConnection<SqliteConnectionManager>>),
+}
+
+static DATABASE_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static TRANSACTION: RefCell<Option<ActiveTransaction>> = const { RefCell::new(None) };
+}
+
+impl Database {
+    pub fn open(path: &str, pool_size: u32, busy_timeout: Duration, wal: bool, synchronous: &'static str) -> Res<Self> {
+        let journal_mode = if wal { "WAL" } else { "DELETE" };
+        let manager = SqliteConnectionManager::file(path).with_init(move |connection| {
+            connection.busy_timeout(busy_timeout)?;
+            // the random order of a list is paged in sql, the same seed has to give the same order on every connection
+            connection.create_scalar_function("shuffle", 2, FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC, |context| {
+                Ok(item::shuffle(context.get::<i64>(0)? as u64, context.get(1)?))
+            })?;
+            connection.execute_batch(&format!("PRAGMA journal_mode = {}; PRAGMA synchronous = {};", journal_mode, synchronous))
+        });
+        let pool = Pool::builder()
+            .max_size(pool_size)
+            .connection_timeout(busy_timeout)
+            .build(manager)
+            .map_err(|e| Error::ConnectError(e.to_string()))?;
+        let database = Self { id: DATABASE_ID.fetch_add(1, Ordering::Relaxed), pool };
+        migration::migrate(&mut *database.get()?)?;
+        Ok(database)
+    }
+
+    pub(super) fn con(&self) -> Res<Con> {
+        let shared = TRANSACTION.with(|tx| {
+            tx.borrow().as_ref()
+                .filter(|active| active.database == self.id)
+                .map(|active| active.connection.clone())
+        });
+        match shared {
+            Some(connection) => Ok(Con::Shared(connection)),
+            None => Ok(Con::Pooled(self.get()?)),
+        }
+    }
+
+    pub fn begin(&self) -> Res<Transaction> {
+        TRANSACTION.with(|tx| {
+            let mut tx = tx.borrow_mut();
+            match tx.as_mut() {
+                Some(active) if active.database == self.id => active.depth += 1,
+                Some(_) => return Err(Error::TransactionError(String::from("another database is in transaction"))),
+                None => {
+                    let connection = self.get()?;
+                    connection.execute_batch("BEGIN IMMEDIATE").map_err(|e| Error::TransactionError(e.to_string()))?;
+                    *tx = Some(ActiveTransaction { database: self.id, connection: Rc::new(connection), depth: 1, rollback_only: false });
+                }
+            }
+            Ok(Transaction { database: self.id, finished: false })
+        })
+    }
+
+    fn get(&self) -> Res<PooledConnection<SqliteConnectionManager>> {
+        self.pool.get().map_err(
+            |_| Error::Busy("lock fail".to_string())
+        )
+    }
+}
+
+impl Transaction {
+    pub fn commit(mut self) -> Res<()> {
+        self.finished = true;
+        Self::finish(self.database, true)
+    }
 
-pub(super) fn con() -> Res<PooledConnection<SqliteConnectionManager>> {
-    POOL.get().map_err(
-        |_| Error::Busy("lock fail".to_string())
-    )
+    pub fn rollback(mut self) -> Res<()> {
+        self.finished = true;
+        Self::finish(self.database, false)
+    }
+
+    fn finish(database: usize, commit: bool) -> Res<()> {
+        TRANSACTION.with(|tx| {
+            let mut tx = tx.borrow_mut();
+            match tx.as_mut() {
+                Some(active) if active.database == database => {
+                    active.depth -= 1;
+                    active.rollback_only |= !commit;
+                    if active.depth > 0 {
+                        return Ok(());
+                    }
+                }
+                _ => return Ok(()),
+            }
+
+            let active = tx.take().unwrap();
+            if active.rollback_only {
+                active.connection.execute_batch("ROLLBACK").map_err(|e| Error::TransactionError(e.to_string()))?;
+                if commit {
+                    return Err(Error::TransactionError(String::from("transaction has been rolled back")));
+                }
+                Ok(())
+            } else {
+                active.connection.execute_batch("COMMIT").map_err(|e| Error::TransactionError(e.to_string()))
+            }
+        })
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = Self::finish(self.database, false);
+        }
+    }
+}
+
+impl Deref for Con {
+    type Target = Connection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Con::Pooled(connection) => connection,
+            Con::Shared(connection) => connection,
+        }
+    }
 }
 
-pub(super) fn insert<P: Params>(sql: &str, params: P) -> Res<i64> {
-    let connection = con()?;
+pub(super) fn insert<P: Params>(db: &Database, sql: &str, params: P) -> Res<i64> {
+    let connection = db.con()?;
     let result = match connection.prepare(sql) {
         Ok(mut stat) =>
             match stat.execute(params) {
@@ -63,8 +210,8 @@ pub(super) fn insert<P: Params>(sql: &str, params: P) -> Res<i64> {
     result
 }
 
-pub(super) fn exec<P: Params>(sql: &str, params: P) -> Res<usize> {
-    match con()?.prepare(sql) {
+pub(super) fn exec<P: Params>(db: &Database, sql: &str, params: P) -> Res<usize> {
+    match db.con()?.prepare(sql) {
         Ok(mut stat) =>
             match stat.execute(params) {
                 Ok(res) => Ok(res),
@@ -74,12 +221,12 @@ pub(super) fn exec<P: Params>(sql: &str, params: P) -> Res<usize> {
     }
 }
 
-pub(super) fn query_one<T, P, F>(sql: &str, params: P, f: F) -> Res<T>
+pub(super) fn query_one<T, P, F>(db: &Database, sql: &str, params: P, f: F) -> Res<T>
 where
     P: Params,
     F: FnOnce(&RowData<'_>) -> Res<T>,
 {
-    match con()?.prepare(sql) {
+    match db.con()?.prepare(sql) {
         Ok(mut stat) =>
             match stat.query_row(params, |x| cast(f(&RowData { row: x }))) {
                 Ok(res) => Ok(res),
@@ -89,12 +236,12 @@ where
     }
 }
 
-pub(super) fn query_all<T, P, F>(sql: &str, params: P, mut f: F) -> Res<Vec<T>>
+pub(super) fn query_all<T, P, F>(db: &Database, sql: &str, params: P, mut f: F) -> Res<Vec<T>>
 where
     P: Params,
     F: FnMut(&RowData<'_>) -> Res<T>,
 {
-    match con()?.prepare(sql) {
+    match db.con()?.prepare(sql) {
         Ok(mut stat) =>
             match stat.query_map(params, |x| cast(f(&RowData { row: x }))) {
                 Ok(res) => Ok(res.filter(|r| r.is_ok()).map(|r| r.unwrap()).collect()),
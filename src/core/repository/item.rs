@@ -1,7 +1,9 @@
 use crate::common::{Error, Res};
-use crate::core::repository::holder::{cast_list, cast_placeholder, exec, insert, map_id, query_all, query_one, update_check, RowData};
-use rusqlite::params;
+use crate::core::repository::holder::{cast_list, cast_placeholder, exec, insert, map_id, query_all, query_one, update_check, Database, RowData};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter};
 
+#[derive(Clone)]
 pub struct ItemStorage {
     pub id: i64,
     pub name: String,
@@ -12,66 +14,262 @@ pub struct ItemStorage {
     pub repo_id: i64,
     pub path: String,
     pub extend: String,
+    pub caption: Option<String>,
+    pub hash: Option<String>,
+    pub deleted_at: Option<i64>,
 }
 
-pub fn create(item: &ItemStorage) -> Res<i64> {
-    insert("INSERT INTO items (name, ext, size, created_at, is_deleted, repo_id, path, extend) VALUES (?, ?, ?, DATETIME('NOW'), false, ?, ?, ?)",
-           params![item.name, item.ext, item.size, item.repo_id, item.path, item.extend])
+// a row of item_geo
+#[derive(Clone)]
+pub struct GeoStorage {
+    pub item_id: i64,
+    pub latitude: f64,
+    pub longitude: f64,
 }
 
-pub fn import(item: &ItemStorage) -> Res<i64> {
-    insert("INSERT INTO items (name, ext, size, created_at, is_deleted, repo_id, path, extend) VALUES (?, ?, ?, ?, false, ?, ?, ?)",
-           params![item.name, item.ext, item.size, item.created_at, item.repo_id, item.path, item.extend])
+// a row of item_phash
+#[derive(Clone)]
+pub struct PhashStorage {
+    pub item_id: i64,
+    pub phash: String,
 }
 
-pub fn select_by_id(id: i64) -> Res<ItemStorage> {
-    query_one("SELECT * FROM items WHERE id = ?", params![id], map)
+// the name of a live item, names are put in natural order outside of sqlite
+#[derive(Clone)]
+pub struct NameStorage {
+    pub item_id: i64,
+    pub name: String,
+}
+
+// what sqlite can order items by besides their id, width, height and taken_at come from the extend
+#[derive(Clone, Copy)]
+pub enum SortColumn {
+    Size,
+    Area,
+    AspectRatio,
+    TakenAt,
+    TagCount,
+    // shuffle of the id with the seed
+    Random(u64),
+}
+
+// the live items of a repo with start_id <= id < end_id ordered by the column and then by the id,
+// items without a value come last either way
+pub struct SortQuery<'a> {
+    pub repo_id: i64,
+    pub start_id: i64,
+    pub end_id: i64,
+    // only these items, when a condition picked them
+    pub ids: Option<&'a [i64]>,
+    pub column: SortColumn,
+    pub descending: bool,
+    // value and id of the item the previous page ended with
+    pub after: Option<(Option<f64>, i64)>,
+}
+
+#[derive(Clone)]
+pub struct SortValueStorage {
+    pub item_id: i64,
+    pub value: Option<f64>,
+}
+
+pub fn create(db: &Database, item: &ItemStorage) -> Res<i64> {
+    insert(db, "INSERT INTO items (name, ext, size, created_at, is_deleted, repo_id, path, extend, caption, hash) VALUES (?, ?, ?, DATETIME('NOW'), false, ?, ?, ?, ?, ?)",
+           params![item.name, item.ext, item.size, item.repo_id, item.path, item.extend, item.caption, item.hash])
+}
+
+pub fn import(db: &Database, item: &ItemStorage) -> Res<i64> {
+    insert(db, "INSERT INTO items (name, ext, size, created_at, is_deleted, repo_id, path, extend, caption, hash) VALUES (?, ?, ?, ?, false, ?, ?, ?, ?, ?)",
+           params![item.name, item.ext, item.size, item.created_at, item.repo_id, item.path, item.extend, item.caption, item.hash])
+}
+
+pub fn select_by_id(db: &Database, id: i64) -> Res<ItemStorage> {
+    query_one(db, "SELECT * FROM items WHERE id = ?", params![id], map)
 }
 
-pub fn select_start_time(repo_id: i64, start_time: i64) -> Res<i64> {
-    query_one("SELECT MIN(id) FROM items WHERE repo_id = ? AND created_at >= ? AND is_deleted = false", params![repo_id, start_time], map_id)
+pub fn select_start_time(db: &Database, repo_id: i64, start_time: i64) -> Res<i64> {
+    query_one(db, "SELECT MIN(id) FROM items WHERE repo_id = ? AND created_at >= ? AND is_deleted = false", params![repo_id, start_time], map_id)
 }
 
-pub fn select_end_time(repo_id: i64, end_time: i64) -> Res<i64> {
-    query_one("SELECT MAX(id) FROM items WHERE repo_id = ? AND created_at < ? AND is_deleted = false", params![repo_id, end_time], map_id)
+pub fn select_end_time(db: &Database, repo_id: i64, end_time: i64) -> Res<i64> {
+    query_one(db, "SELECT MAX(id) FROM items WHERE repo_id = ? AND created_at < ? AND is_deleted = false", params![repo_id, end_time], map_id)
 }
 
-pub fn select_min_max_id(repo_id: i64) -> Res<(i64, i64)> {
-    query_one("SELECT MIN(id), MAX(id) FROM items WHERE repo_id = ? AND is_deleted = false", params![repo_id], |row| {
+pub fn select_min_max_id(db: &Database, repo_id: i64) -> Res<(i64, i64)> {
+    query_one(db, "SELECT MIN(id), MAX(id) FROM items WHERE repo_id = ? AND is_deleted = false", params![repo_id], |row| {
         Ok((row.get::<i64>(0)?, row.get::<i64>(1)?))
     })
 }
 
-pub fn select_item_by_ids(ids: &Vec<i64>) -> Res<Vec<ItemStorage>> {
-    query_all(&format!("SELECT * FROM items WHERE id IN ({})", cast_placeholder(ids)), cast_list(ids).as_slice(), map)
+pub fn select_item_by_ids(db: &Database, ids: &Vec<i64>) -> Res<Vec<ItemStorage>> {
+    query_all(db, &format!("SELECT * FROM items WHERE id IN ({}) AND is_deleted = false", cast_placeholder(ids)), cast_list(ids).as_slice(), map)
+}
+
+pub fn select_by_hash(db: &Database, repo_id: i64, hash: &str) -> Res<Vec<ItemStorage>> {
+    query_all(db, "SELECT * FROM items WHERE repo_id = ? AND hash = ? AND is_deleted = false ORDER BY id ASC", params![repo_id, hash], map)
+}
+
+pub fn select_from(db: &Database, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+    query_all(db, "SELECT * FROM items WHERE repo_id = ? AND id >= ? AND id < ? AND is_deleted = false ORDER BY id ASC LIMIT ?", params![repo_id, start_id, end_id, limit], map)
+}
+
+pub fn select_to(db: &Database, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+    query_all(db, "SELECT * FROM items WHERE repo_id = ? AND id >= ? AND id < ? AND is_deleted = false ORDER BY id DESC LIMIT ?", params![repo_id, start_id, end_id, limit], map)
+}
+
+pub fn count_range(db: &Database, repo_id: i64, start_id: i64, end_id: i64) -> Res<i64> {
+    query_one(db, "SELECT COUNT(*) FROM items WHERE repo_id = ? AND id >= ? AND id < ? AND is_deleted = false", params![repo_id, start_id, end_id], |row| row.get::<i64>(0))
+}
+
+pub fn mark_delete_item(db: &Database, id: i64) -> Res<()> {
+    update_check(exec(db, "UPDATE items SET is_deleted = true, deleted_at = DATETIME('NOW') WHERE id = ?", params![id]), Error::ItemNotFound)
+}
+
+pub fn update_item(db: &Database, item: &ItemStorage) -> Res<()> {
+    update_check(exec(db, "UPDATE items SET name = ?, ext = ?, size = ?, extend = ?, caption = ? WHERE id = ?", params![item.name, item.ext,  item.size, item.extend, item.caption, item.id]), Error::ItemNotFound)
 }
 
-pub fn select_from(repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
-    query_all("SELECT * FROM items WHERE repo_id = ? AND id >= ? AND id < ? AND is_deleted = false ORDER BY id ASC LIMIT ?", params![repo_id, start_id, end_id, limit], map)
+pub fn change_path(db: &Database, id: i64, path: &str) -> Res<()> {
+    update_check(exec(db, "UPDATE items SET path = ? WHERE id = ?", params![path, id]), Error::ItemNotFound)
 }
 
-pub fn select_to(repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
-    query_all("SELECT * FROM items WHERE repo_id = ? AND id >= ? AND id < ? AND is_deleted = false ORDER BY id DESC LIMIT ?", params![repo_id, start_id, end_id, limit], map)
+pub fn change_repo(db: &Database, id: i64, repo_id: i64) -> Res<()> {
+    update_check(exec(db, "UPDATE items SET repo_id = ? WHERE id = ?", params![repo_id, id]), Error::ItemNotFound)
 }
 
-pub fn mark_delete_item(id: i64) -> Res<()> {
-    update_check(exec("UPDATE items SET is_deleted = true WHERE id = ?", params![id]), Error::ItemNotFound)
+pub fn reset_item(db: &Database, id: i64) -> Res<()> {
+    update_check(exec(db, "UPDATE items SET is_deleted = false, deleted_at = NULL WHERE id = ?", params![id]), Error::ItemNotFound)
 }
 
-pub fn update_item(item: &ItemStorage) -> Res<()> {
-    update_check(exec("UPDATE items SET name = ?, ext = ?, size = ?, extend = ? WHERE id = ?", params![item.name, item.ext,  item.size, item.extend, item.id]), Error::ItemNotFound)
+// the row is gone for good, the triggers take its search, geo and phash rows with it
+pub fn purge_item(db: &Database, id: i64) -> Res<()> {
+    update_check(exec(db, "DELETE FROM items WHERE id = ?", params![id]), Error::ItemNotFound)
 }
 
-pub fn change_path(id: i64, path: &str) -> Res<()> {
-    update_check(exec("UPDATE items SET path = ? WHERE id = ?", params![path, id]), Error::ItemNotFound)
+pub fn select_deleted(db: &Database, repo_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+    query_all(db, "SELECT * FROM items WHERE repo_id = ? AND id < ? AND is_deleted = true ORDER BY id DESC LIMIT ?", params![repo_id, end_id, limit], map)
+}
+
+pub fn select_expired(db: &Database, repo_id: i64, deleted_before: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+    query_all(db, "SELECT * FROM items WHERE repo_id = ? AND is_deleted = true AND deleted_at < DATETIME(?, 'unixepoch') ORDER BY id ASC LIMIT ?", params![repo_id, deleted_before, limit], map)
+}
+
+// linked duplicates share one file, deleted items included since they may come back
+pub fn count_by_path(db: &Database, repo_id: i64, path: &str) -> Res<usize> {
+    query_one(db, "SELECT COUNT(*) FROM items WHERE repo_id = ? AND path = ?", params![repo_id, path], |row| row.get::<usize>(0))
+}
+
+// the trigram tokenizer cannot match terms shorter than 3 characters, those fall back to an unranked LIKE scan
+pub fn search(db: &Database, repo_id: i64, keyword: &str) -> Res<Vec<i64>> {
+    let terms: Vec<&str> = keyword.split_whitespace().collect();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    if terms.iter().all(|term| term.chars().count() >= 3) {
+        let query = terms.iter().map(|term| format!("\"{}\"", term.replace('"', "\"\""))).collect::<Vec<_>>().join(" ");
+        query_all(db, "SELECT s.rowid FROM item_search s JOIN items i ON i.id = s.rowid WHERE item_search MATCH ? AND i.repo_id = ? AND i.is_deleted = false ORDER BY s.rank",
+                  params![query, repo_id], map_id)
+    } else {
+        let pattern = format!("%{}%", keyword.trim().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        query_all(db, "SELECT s.rowid FROM item_search s JOIN items i ON i.id = s.rowid WHERE (s.name LIKE ?1 ESCAPE '\\' OR s.caption LIKE ?1 ESCAPE '\\' OR s.tags LIKE ?1 ESCAPE '\\') AND i.repo_id = ?2 AND i.is_deleted = false ORDER BY i.id DESC",
+                  params![pattern, repo_id], map_id)
+    }
+}
+
+// a box crossing the antimeridian has min_lng > max_lng
+pub fn select_geo(db: &Database, repo_id: i64, min_lat: f64, max_lat: f64, min_lng: f64, max_lng: f64) -> Res<Vec<GeoStorage>> {
+    let longitude = if min_lng <= max_lng { "g.longitude BETWEEN ?4 AND ?5" } else { "(g.longitude >= ?4 OR g.longitude <= ?5)" };
+    query_all(db, &format!("SELECT g.item_id, g.latitude, g.longitude FROM item_geo g JOIN items i ON i.id = g.item_id WHERE g.repo_id = ?1 AND i.is_deleted = false AND g.latitude BETWEEN ?2 AND ?3 AND {}", longitude),
+              params![repo_id, min_lat, max_lat, min_lng, max_lng], |row| {
+        Ok(GeoStorage { item_id: row.get(0)?, latitude: row.get(1)?, longitude: row.get(2)? })
+    })
+}
+
+pub fn select_phash(db: &Database, repo_id: i64) -> Res<Vec<PhashStorage>> {
+    query_all(db, "SELECT p.item_id, p.phash FROM item_phash p JOIN items i ON i.id = p.item_id WHERE p.repo_id = ? AND i.is_deleted = false ORDER BY p.item_id",
+              params![repo_id], |row| {
+        Ok(PhashStorage { item_id: row.get(0)?, phash: row.get(1)? })
+    })
+}
+
+pub fn select_names(db: &Database, repo_id: i64, start_id: i64, end_id: i64) -> Res<Vec<NameStorage>> {
+    query_all(db, "SELECT id, name FROM items WHERE repo_id = ? AND id >= ? AND id < ? AND is_deleted = false",
+              params![repo_id, start_id, end_id], map_name)
+}
+
+pub fn select_names_by_ids(db: &Database, ids: &Vec<i64>) -> Res<Vec<NameStorage>> {
+    query_all(db, &format!("SELECT id, name FROM items WHERE id IN ({}) AND is_deleted = false", cast_placeholder(ids)),
+              cast_list(ids).as_slice(), map_name)
+}
+
+pub fn select_sorted(db: &Database, query: &SortQuery<'_>, limit: i64) -> Res<Vec<SortValueStorage>> {
+    let (sql, mut values) = sorted_sql(query, "id, k");
+    let direction = if query.descending { "DESC" } else { "ASC" };
+    values.push(Value::Integer(limit));
+    query_all(db, &format!("{} ORDER BY k IS NULL, k {}, id {} LIMIT ?", sql, direction, direction), params_from_iter(values), |row| {
+        Ok(SortValueStorage { item_id: row.get(0)?, value: row.get(1)? })
+    })
+}
+
+pub fn count_sorted(db: &Database, query: &SortQuery<'_>) -> Res<i64> {
+    let (sql, values) = sorted_sql(query, "COUNT(*)");
+    query_one(db, &sql, params_from_iter(values), |row| row.get(0))
+}
+
+const WIDTH: &str = "COALESCE(json_extract(i.extend, '$.Picture.image_extend.w'), json_extract(i.extend, '$.Photo.image_extend.w'))";
+const HEIGHT: &str = "COALESCE(json_extract(i.extend, '$.Picture.image_extend.h'), json_extract(i.extend, '$.Photo.image_extend.h'))";
+
+// only the value of the column asked for is computed, the keyset on (k, id) picks up after the previous page
+fn sorted_sql(query: &SortQuery<'_>, select: &str) -> (String, Vec<Value>) {
+    let mut values = Vec::new();
+    let key = match query.column {
+        SortColumn::Size => String::from("i.size"),
+        SortColumn::Area => format!("CASE WHEN {} > 0 THEN {} * {} END", HEIGHT, WIDTH, HEIGHT),
+        SortColumn::AspectRatio => format!("CASE WHEN {} > 0 THEN CAST({} AS REAL) / {} END", HEIGHT, WIDTH, HEIGHT),
+        SortColumn::TakenAt => String::from("CAST(strftime('%s', json_extract(i.extend, '$.Photo.exif.taken_at')) AS INTEGER)"),
+        SortColumn::TagCount => String::from("(SELECT COUNT(*) FROM item_tag_relation r JOIN tags t ON t.id = r.tag_id WHERE r.item_id = i.id AND r.is_delete = false AND t.is_delete = false)"),
+        SortColumn::Random(seed) => {
+            values.push(Value::Integer(seed as i64));
+            String::from("shuffle(?, i.id)")
+        }
+    };
+    values.extend([Value::Integer(query.repo_id), Value::Integer(query.start_id), Value::Integer(query.end_id)]);
+    let ids = match query.ids {
+        Some(ids) => {
+            // one parameter whatever the number of ids
+            values.push(Value::Text(format!("[{}]", ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(","))));
+            " AND i.id IN (SELECT value FROM json_each(?))"
+        }
+        None => "",
+    };
+    let op = if query.descending { "<" } else { ">" };
+    let after = match query.after {
+        None => String::new(),
+        Some((Some(value), id)) => {
+            values.extend([Value::Real(value), Value::Real(value), Value::Integer(id)]);
+            format!(" WHERE k IS NULL OR k {} ? OR (k = ? AND id {} ?)", op, op)
+        }
+        Some((None, id)) => {
+            values.push(Value::Integer(id));
+            format!(" WHERE k IS NULL AND id {} ?", op)
+        }
+    };
+    let sql = format!("SELECT {} FROM (SELECT i.id AS id, {} AS k FROM items i WHERE i.repo_id = ? AND i.id >= ? AND i.id < ? AND i.is_deleted = false{}){}",
+                      select, key, ids, after);
+    (sql, values)
 }
 
-pub fn change_repo(id: i64, repo_id: i64) -> Res<()> {
-    update_check(exec("UPDATE items SET repo_id = ? WHERE id = ?", params![repo_id, id]), Error::ItemNotFound)
+// splitmix64 of the id, the top 53 bits so the value is exact as a f64 as well
+pub(super) fn shuffle(seed: u64, id: i64) -> i64 {
+    let mut z = (seed ^ id as u64).wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    ((z ^ (z >> 31)) >> 11) as i64
 }
 
-pub fn reset_item(id: i64) -> Res<()> {
-    update_check(exec("UPDATE items SET is_deleted = false WHERE id = ?", params![id]), Error::ItemNotFound)
+fn map_name(row: &RowData<'_>) -> Res<NameStorage> {
+    Ok(NameStorage { item_id: row.get(0)?, name: row.get(1)? })
 }
 
 fn map(row: &RowData<'_>) -> Res<ItemStorage> {
@@ -85,5 +283,8 @@ fn map(row: &RowData<'_>) -> Res<ItemStorage> {
         repo_id: row.get(6)?,
         path: row.get(7)?,
         extend: row.get(8)?,
+        caption: row.get(9)?,
+        hash: row.get(10)?,
+        deleted_at: row.get_optional_timestamp(11)?,
     })
 }
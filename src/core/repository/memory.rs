@@ -0,0 +1,708 @@
+use crate::common::{Error, Res};
+use crate::core::repository::store::{ItemStore, RepoStore, TagStore, TransactionHandle, UnitOfWork, UserStore};
+use crate::core::repository::item::shuffle;
+use crate::core::repository::{GeoStorage, ItemStorage, NameStorage, PhashStorage, SortColumn, SortQuery, SortValueStorage, ItemTagRelation, RepoStorage, TagStorage, UserRepoRoleStorage, UserStorage};
+use chrono::{NaiveDateTime, Utc};
+use std::cell::RefCell;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+// throwaway storage for tests, a transaction works on its own copy of the data and swaps it in on commit
+pub struct MemoryStore {
+    state: Arc<MemoryState>,
+}
+
+struct MemoryState {
+    id: usize,
+    data: Mutex<MemoryData>,
+    // one writer at a time like BEGIN IMMEDIATE, so a commit never drops what another thread wrote
+    writing: Mutex<bool>,
+    idle: Condvar,
+}
+
+#[derive(Clone, Default)]
+struct MemoryData {
+    next_id: i64,
+    items: BTreeMap<i64, ItemStorage>,
+    tags: BTreeMap<i64, TagStorage>,
+    relations: BTreeMap<i64, ItemTagRelation>,
+    users: BTreeMap<i64, UserStorage>,
+    roles: BTreeMap<i64, UserRepoRoleStorage>,
+    repos: BTreeMap<i64, RepoStorage>,
+}
+
+// the transaction opened by the current thread, every call of the same store goes through its copy
+struct ActiveTransaction {
+    store: usize,
+    data: MemoryData,
+    depth: usize,
+    rollback_only: bool,
+}
+
+struct MemoryTransaction {
+    state: Arc<MemoryState>,
+    finished: bool,
+}
+
+static STORE_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static TRANSACTION: RefCell<Option<ActiveTransaction>> = const { RefCell::new(None) };
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(MemoryState {
+                id: STORE_ID.fetch_add(1, Ordering::Relaxed),
+                data: Mutex::new(MemoryData::default()),
+                writing: Mutex::new(false),
+                idle: Condvar::new(),
+            })
+        }
+    }
+
+    fn read<F, R>(&self, f: F) -> Res<R>
+    where
+        F: FnOnce(&MemoryData) -> Res<R>,
+    {
+        let mut f = Some(f);
+        match self.state.active(|data| f.take().unwrap()(data)) {
+            Some(res) => res,
+            None => f.take().unwrap()(&*self.state.data.lock()?),
+        }
+    }
+
+    fn write<F, R>(&self, f: F) -> Res<R>
+    where
+        F: FnOnce(&mut MemoryData) -> Res<R>,
+    {
+        // a write outside of a transaction commits at once, it still waits for the running one
+        let mut f = Some(f);
+        if let Some(res) = self.state.active(|data| f.take().unwrap()(data)) {
+            return res;
+        }
+        self.state.acquire()?;
+        let res = self.state.data.lock().map_err(Error::from).and_then(|mut data| f.take().unwrap()(&mut data));
+        self.state.release()?;
+        res
+    }
+}
+
+impl MemoryState {
+    // None when the current thread has no transaction on this store
+    fn active<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut MemoryData) -> R,
+    {
+        TRANSACTION.with(|tx| {
+            tx.borrow_mut().as_mut()
+                .filter(|active| active.store == self.id)
+                .map(|active| f(&mut active.data))
+        })
+    }
+
+    fn acquire(&self) -> Res<()> {
+        let mut writing = self.writing.lock()?;
+        while *writing {
+            writing = self.idle.wait(writing)?;
+        }
+        *writing = true;
+        Ok(())
+    }
+
+    fn release(&self) -> Res<()> {
+        *self.writing.lock()? = false;
+        self.idle.notify_one();
+        Ok(())
+    }
+}
+
+impl MemoryData {
+    fn next_id(&mut self) -> i64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+impl MemoryTransaction {
+    fn finish(&mut self, commit: bool) -> Res<()> {
+        self.finished = true;
+        let active = TRANSACTION.with(|tx| {
+            let mut tx = tx.borrow_mut();
+            match tx.as_mut() {
+                Some(active) if active.store == self.state.id => {
+                    active.depth -= 1;
+                    active.rollback_only |= !commit;
+                    if active.depth > 0 {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+            tx.take()
+        });
+        let Some(active) = active else {
+            return Ok(());
+        };
+
+        let res = match active.rollback_only {
+            false => self.state.data.lock().map(|mut data| *data = active.data).map_err(Error::from),
+            true if commit => Err(Error::TransactionError(String::from("transaction has been rolled back"))),
+            true => Ok(()),
+        };
+        self.state.release()?;
+        res
+    }
+}
+
+impl TransactionHandle for MemoryTransaction {
+    fn commit(mut self: Box<Self>) -> Res<()> {
+        self.finish(true)
+    }
+
+    fn rollback(mut self: Box<Self>) -> Res<()> {
+        self.finish(false)
+    }
+}
+
+impl Drop for MemoryTransaction {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.finish(false);
+        }
+    }
+}
+
+impl UnitOfWork for MemoryStore {
+    fn begin(&self) -> Res<Box<dyn TransactionHandle>> {
+        let nested = TRANSACTION.with(|tx| {
+            let mut tx = tx.borrow_mut();
+            match tx.as_mut() {
+                Some(active) if active.store == self.state.id => {
+                    active.depth += 1;
+                    Ok(true)
+                }
+                Some(_) => Err(Error::TransactionError(String::from("another store is in transaction"))),
+                None => Ok(false),
+            }
+        })?;
+        if !nested {
+            self.state.acquire()?;
+            let data = match self.state.data.lock() {
+                Ok(data) => data.clone(),
+                Err(e) => {
+                    self.state.release()?;
+                    return Err(e.into());
+                }
+            };
+            TRANSACTION.with(|tx| *tx.borrow_mut() = Some(ActiveTransaction { store: self.state.id, data, depth: 1, rollback_only: false }));
+        }
+        Ok(Box::new(MemoryTransaction { state: self.state.clone(), finished: false }))
+    }
+}
+
+fn update_one<T, F>(map: &mut BTreeMap<i64, T>, id: i64, err: Error, f: F) -> Res<()>
+where
+    F: FnOnce(&mut T),
+{
+    match map.get_mut(&id) {
+        None => Err(err),
+        Some(value) => {
+            f(value);
+            Ok(())
+        }
+    }
+}
+
+impl ItemStore for MemoryStore {
+    fn create(&self, item: &ItemStorage) -> Res<i64> {
+        self.write(|data| {
+            let id = data.next_id();
+            let item = ItemStorage { id, created_at: Utc::now().timestamp(), is_deleted: false, deleted_at: None, ..item.clone() };
+            data.items.insert(id, item);
+            Ok(id)
+        })
+    }
+
+    fn import(&self, item: &ItemStorage) -> Res<i64> {
+        self.write(|data| {
+            let id = data.next_id();
+            data.items.insert(id, ItemStorage { id, is_deleted: false, deleted_at: None, ..item.clone() });
+            Ok(id)
+        })
+    }
+
+    fn select_by_id(&self, id: i64) -> Res<ItemStorage> {
+        self.read(|data| data.items.get(&id).cloned().ok_or(Error::ItemNotFound))
+    }
+
+    fn select_start_time(&self, repo_id: i64, start_time: i64) -> Res<i64> {
+        self.read(|data| {
+            data.items.values()
+                .find(|item| item.repo_id == repo_id && !item.is_deleted && item.created_at >= start_time)
+                .map(|item| item.id)
+                .ok_or(Error::ItemNotFound)
+        })
+    }
+
+    fn select_end_time(&self, repo_id: i64, end_time: i64) -> Res<i64> {
+        self.read(|data| {
+            data.items.values().rev()
+                .find(|item| item.repo_id == repo_id && !item.is_deleted && item.created_at < end_time)
+                .map(|item| item.id)
+                .ok_or(Error::ItemNotFound)
+        })
+    }
+
+    fn select_min_max_id(&self, repo_id: i64) -> Res<(i64, i64)> {
+        self.read(|data| {
+            let mut ids = data.items.values()
+                .filter(|item| item.repo_id == repo_id && !item.is_deleted)
+                .map(|item| item.id);
+            let min = ids.next().ok_or(Error::ItemNotFound)?;
+            Ok((min, ids.next_back().unwrap_or(min)))
+        })
+    }
+
+    fn select_by_ids(&self, ids: &Vec<i64>) -> Res<Vec<ItemStorage>> {
+        self.read(|data| Ok(ids.iter().filter_map(|id| data.items.get(id)).filter(|item| !item.is_deleted).cloned().collect()))
+    }
+
+    fn select_by_hash(&self, repo_id: i64, hash: &str) -> Res<Vec<ItemStorage>> {
+        self.read(|data| {
+            Ok(data.items.values()
+                .filter(|item| item.repo_id == repo_id && !item.is_deleted && item.hash.as_deref() == Some(hash))
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn select_from(&self, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+        self.read(|data| {
+            Ok(data.items.range(start_id..end_id.max(start_id)).map(|(_, item)| item)
+                .filter(|item| item.repo_id == repo_id && !item.is_deleted)
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn select_to(&self, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+        self.read(|data| {
+            Ok(data.items.range(start_id..end_id.max(start_id)).rev().map(|(_, item)| item)
+                .filter(|item| item.repo_id == repo_id && !item.is_deleted)
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn count_range(&self, repo_id: i64, start_id: i64, end_id: i64) -> Res<i64> {
+        self.read(|data| {
+            Ok(data.items.range(start_id..end_id.max(start_id))
+                .filter(|(_, item)| item.repo_id == repo_id && !item.is_deleted)
+                .count() as i64)
+        })
+    }
+
+    fn mark_delete(&self, id: i64) -> Res<()> {
+        self.write(|data| update_one(&mut data.items, id, Error::ItemNotFound, |item| {
+            item.is_deleted = true;
+            item.deleted_at = Some(Utc::now().timestamp());
+        }))
+    }
+
+    fn update(&self, item: &ItemStorage) -> Res<()> {
+        self.write(|data| update_one(&mut data.items, item.id, Error::ItemNotFound, |old| {
+            old.name = item.name.clone();
+            old.ext = item.ext;
+            old.size = item.size;
+            old.extend = item.extend.clone();
+            old.caption = item.caption.clone();
+        }))
+    }
+
+    fn change_path(&self, id: i64, path: &str) -> Res<()> {
+        self.write(|data| update_one(&mut data.items, id, Error::ItemNotFound, |item| item.path = String::from(path)))
+    }
+
+    fn change_repo(&self, id: i64, repo_id: i64) -> Res<()> {
+        self.write(|data| update_one(&mut data.items, id, Error::ItemNotFound, |item| item.repo_id = repo_id))
+    }
+
+    fn reset(&self, id: i64) -> Res<()> {
+        self.write(|data| update_one(&mut data.items, id, Error::ItemNotFound, |item| {
+            item.is_deleted = false;
+            item.deleted_at = None;
+        }))
+    }
+
+    fn purge(&self, id: i64) -> Res<()> {
+        self.write(|data| data.items.remove(&id).map(|_| ()).ok_or(Error::ItemNotFound))
+    }
+
+    fn select_deleted(&self, repo_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+        self.read(|data| {
+            Ok(data.items.range(..end_id).rev()
+                .map(|(_, item)| item)
+                .filter(|item| item.repo_id == repo_id && item.is_deleted)
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn select_expired(&self, repo_id: i64, deleted_before: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+        self.read(|data| {
+            Ok(data.items.values()
+                .filter(|item| item.repo_id == repo_id && item.is_deleted && item.deleted_at.is_some_and(|deleted_at| deleted_at < deleted_before))
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn count_by_path(&self, repo_id: i64, path: &str) -> Res<usize> {
+        self.read(|data| Ok(data.items.values().filter(|item| item.repo_id == repo_id && item.path == path).count()))
+    }
+
+    // plain substring match, weighted like the bm25 columns of the sqlite index
+    fn search(&self, repo_id: i64, keyword: &str) -> Res<Vec<i64>> {
+        let terms: Vec<String> = keyword.split_whitespace().map(|term| term.to_lowercase()).collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.read(|data| {
+            let mut scored: Vec<(usize, i64)> = data.items.values()
+                .filter(|item| item.repo_id == repo_id && !item.is_deleted)
+                .filter_map(|item| {
+                    let name = item.name.to_lowercase();
+                    let caption = item.caption.as_deref().unwrap_or_default().to_lowercase();
+                    let tags = data.relations.values()
+                        .filter(|relation| relation.item_id == item.id && !relation.is_delete)
+                        .filter_map(|relation| data.tags.get(&relation.tag_id))
+                        .filter(|tag| !tag.is_delete)
+                        .map(|tag| tag.name.to_lowercase())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let mut score = 0;
+                    for term in &terms {
+                        let hit = 10 * name.matches(term.as_str()).count()
+                            + 5 * caption.matches(term.as_str()).count()
+                            + 2 * tags.matches(term.as_str()).count();
+                        if hit == 0 {
+                            return None;
+                        }
+                        score += hit;
+                    }
+                    Some((score, item.id))
+                })
+                .collect();
+            scored.sort_unstable_by(|lhs, rhs| rhs.cmp(lhs));
+            Ok(scored.into_iter().map(|(_, id)| id).collect())
+        })
+    }
+
+    // reads the gps straight out of the extend json, like the triggers behind item_geo
+    fn select_geo(&self, repo_id: i64, min_lat: f64, max_lat: f64, min_lng: f64, max_lng: f64) -> Res<Vec<GeoStorage>> {
+        self.read(|data| {
+            Ok(data.items.values()
+                .filter(|item| item.repo_id == repo_id && !item.is_deleted)
+                .filter_map(|item| {
+                    let extend = serde_json::from_str::<serde_json::Value>(&item.extend).ok()?;
+                    let latitude = extend.pointer("/Photo/exif/gps/latitude")?.as_f64()?;
+                    let longitude = extend.pointer("/Photo/exif/gps/longitude")?.as_f64()?;
+                    Some(GeoStorage { item_id: item.id, latitude, longitude })
+                })
+                .filter(|geo| geo.latitude >= min_lat && geo.latitude <= max_lat)
+                .filter(|geo| if min_lng <= max_lng {
+                    geo.longitude >= min_lng && geo.longitude <= max_lng
+                } else {
+                    geo.longitude >= min_lng || geo.longitude <= max_lng
+                })
+                .collect())
+        })
+    }
+
+    fn select_phash(&self, repo_id: i64) -> Res<Vec<PhashStorage>> {
+        self.read(|data| {
+            Ok(data.items.values()
+                .filter(|item| item.repo_id == repo_id && !item.is_deleted)
+                .filter_map(|item| {
+                    let extend = serde_json::from_str::<serde_json::Value>(&item.extend).ok()?;
+                    let phash = extend.pointer("/Picture/image_extend/phash")
+                        .or_else(|| extend.pointer("/Photo/image_extend/phash"))?
+                        .as_str()?;
+                    Some(PhashStorage { item_id: item.id, phash: String::from(phash) })
+                })
+                .collect())
+        })
+    }
+
+    fn select_names(&self, repo_id: i64, start_id: i64, end_id: i64) -> Res<Vec<NameStorage>> {
+        self.read(|data| {
+            Ok(data.items.range(start_id..end_id.max(start_id)).map(|(_, item)| item)
+                .filter(|item| item.repo_id == repo_id && !item.is_deleted)
+                .map(|item| NameStorage { item_id: item.id, name: item.name.clone() })
+                .collect())
+        })
+    }
+
+    fn select_names_by_ids(&self, ids: &[i64]) -> Res<Vec<NameStorage>> {
+        self.read(|data| {
+            Ok(ids.iter().filter_map(|id| data.items.get(id))
+                .filter(|item| !item.is_deleted)
+                .map(|item| NameStorage { item_id: item.id, name: item.name.clone() })
+                .collect())
+        })
+    }
+
+    fn select_sorted(&self, query: &SortQuery<'_>, limit: i64) -> Res<Vec<SortValueStorage>> {
+        self.read(|data| Ok(sorted(data, query).into_iter().take(limit.max(0) as usize).collect()))
+    }
+
+    fn count_sorted(&self, query: &SortQuery<'_>) -> Res<i64> {
+        self.read(|data| Ok(sorted(data, query).len() as i64))
+    }
+}
+
+impl TagStore for MemoryStore {
+    fn create_tag(&self, tag: &TagStorage) -> Res<i64> {
+        self.write(|data| {
+            let id = data.next_id();
+            data.tags.insert(id, TagStorage { id, name: tag.name.clone(), is_delete: false, ..*tag });
+            Ok(id)
+        })
+    }
+
+    fn select_by_id(&self, id: i64) -> Res<TagStorage> {
+        self.read(|data| {
+            data.tags.get(&id)
+                .filter(|tag| !tag.is_delete)
+                .cloned()
+                .ok_or(Error::TagNotFound)
+        })
+    }
+
+    fn select_all(&self, repo_id: i64) -> Res<Vec<TagStorage>> {
+        self.read(|data| Ok(data.tags.values().filter(|tag| tag.repo_id == repo_id && !tag.is_delete).cloned().collect()))
+    }
+
+    fn select_by_ids(&self, ids: &Vec<i64>) -> Res<Vec<TagStorage>> {
+        self.read(|data| Ok(ids.iter().filter_map(|id| data.tags.get(id).cloned()).collect()))
+    }
+
+    fn update_tag(&self, tag: &TagStorage) -> Res<()> {
+        self.write(|data| update_one(&mut data.tags, tag.id, Error::TagNotFound, |old| {
+            old.name = tag.name.clone();
+            old.repo_id = tag.repo_id;
+            old.parent = tag.parent;
+        }))
+    }
+
+    fn delete_tag(&self, id: i64) -> Res<()> {
+        self.write(|data| update_one(&mut data.tags, id, Error::TagNotFound, |tag| tag.is_delete = true))
+    }
+
+    fn reset_tag(&self, id: i64) -> Res<()> {
+        self.write(|data| update_one(&mut data.tags, id, Error::TagNotFound, |tag| tag.is_delete = false))
+    }
+
+    fn create_relation(&self, relation: &ItemTagRelation) -> Res<i64> {
+        self.write(|data| {
+            let id = data.next_id();
+            data.relations.insert(id, ItemTagRelation { id, is_delete: false, ..*relation });
+            Ok(id)
+        })
+    }
+
+    fn delete_relation(&self, id: i64) -> Res<()> {
+        self.write(|data| update_one(&mut data.relations, id, Error::TagRelationNotFound, |relation| relation.is_delete = true))
+    }
+
+    fn delete_relation_by_tag(&self, tag_id: i64) -> Res<usize> {
+        self.write(|data| {
+            let relations = data.relations.values_mut().filter(|relation| relation.tag_id == tag_id);
+            Ok(relations.map(|relation| relation.is_delete = true).count())
+        })
+    }
+
+    fn delete_relation_by_item(&self, item_id: i64) -> Res<usize> {
+        self.write(|data| {
+            let relations = data.relations.values_mut().filter(|relation| relation.item_id == item_id);
+            Ok(relations.map(|relation| relation.is_delete = true).count())
+        })
+    }
+
+    fn purge_relation_by_item(&self, item_id: i64) -> Res<usize> {
+        self.write(|data| {
+            let count = data.relations.len();
+            data.relations.retain(|_, relation| relation.item_id != item_id);
+            Ok(count - data.relations.len())
+        })
+    }
+
+    fn revert_relation_by_tag(&self, tag_id: i64) -> Res<usize> {
+        self.write(|data| {
+            let relations = data.relations.values_mut().filter(|relation| relation.tag_id == tag_id);
+            Ok(relations.map(|relation| relation.is_delete = false).count())
+        })
+    }
+
+    fn select_relation_by_tags(&self, tags: &Vec<i64>) -> Res<Vec<ItemTagRelation>> {
+        self.read(|data| {
+            Ok(data.relations.values()
+                .filter(|relation| tags.contains(&relation.tag_id) && !relation.is_delete)
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn select_relation_by_item(&self, item_id: i64) -> Res<Vec<ItemTagRelation>> {
+        self.read(|data| Ok(data.relations.values().rev().filter(|relation| relation.item_id == item_id).cloned().collect()))
+    }
+
+    fn select_relation_by_items(&self, items: &Vec<i64>) -> Res<Vec<ItemTagRelation>> {
+        self.read(|data| Ok(data.relations.values().filter(|relation| items.contains(&relation.item_id)).cloned().collect()))
+    }
+
+    fn select_relation_by_both(&self, item_id: i64, tag_id: i64) -> Res<ItemTagRelation> {
+        self.read(|data| {
+            data.relations.values()
+                .filter(|relation| relation.item_id == item_id && relation.tag_id == tag_id && !relation.is_delete)
+                .max_by_key(|relation| relation.id)
+                .cloned()
+                .ok_or(Error::TagRelationNotFound)
+        })
+    }
+}
+
+impl UserStore for MemoryStore {
+    fn create_user(&self, user: &UserStorage) -> Res<i64> {
+        self.write(|data| {
+            let id = data.next_id();
+            data.users.insert(id, UserStorage { id, token: None, ..user.clone() });
+            Ok(id)
+        })
+    }
+
+    fn query_by_id(&self, id: i64) -> Res<UserStorage> {
+        self.read(|data| data.users.get(&id).cloned().ok_or(Error::NoSuchUser))
+    }
+
+    fn query_by_name(&self, name: &str) -> Res<UserStorage> {
+        self.read(|data| data.users.values().find(|user| user.name == name).cloned().ok_or(Error::NoSuchUser))
+    }
+
+    fn query_by_token(&self, token: &str) -> Res<UserStorage> {
+        self.read(|data| {
+            data.users.values()
+                .find(|user| user.token.as_deref() == Some(token))
+                .cloned()
+                .ok_or(Error::NoSuchUser)
+        })
+    }
+
+    fn update_token(&self, id: i64, token: &str) -> Res<()> {
+        self.write(|data| update_one(&mut data.users, id, Error::NoSuchUser, |user| user.token = Some(String::from(token))))
+    }
+
+    fn update_password(&self, id: i64, password: &str) -> Res<()> {
+        self.write(|data| update_one(&mut data.users, id, Error::NoSuchUser, |user| user.password = String::from(password)))
+    }
+
+    fn clear_token(&self, id: i64) -> Res<()> {
+        self.write(|data| update_one(&mut data.users, id, Error::NoSuchUser, |user| user.token = None))
+    }
+
+    fn create_user_repo_role(&self, role: &UserRepoRoleStorage) -> Res<i64> {
+        self.write(|data| {
+            let id = data.next_id();
+            data.roles.insert(id, UserRepoRoleStorage { id, ..*role });
+            Ok(id)
+        })
+    }
+
+    fn select_user_role(&self, user_id: i64) -> Res<Vec<UserRepoRoleStorage>> {
+        self.read(|data| Ok(data.roles.values().filter(|role| role.user_id == user_id).cloned().collect()))
+    }
+
+    fn update_user_role(&self, id: i64, role: i64) -> Res<()> {
+        self.write(|data| update_one(&mut data.roles, id, Error::NoSuchUser, |old| old.role = role))
+    }
+}
+
+impl RepoStore for MemoryStore {
+    fn create_repo(&self, repo: &RepoStorage) -> Res<i64> {
+        self.write(|data| {
+            let id = data.next_id();
+            data.repos.insert(id, RepoStorage { id, is_delete: false, ..repo.clone() });
+            Ok(id)
+        })
+    }
+
+    fn update_repo(&self, id: i64, new_name: &str, config: &str) -> Res<()> {
+        self.write(|data| update_one(&mut data.repos, id, Error::NoSuchRepo, |repo| {
+            repo.name = String::from(new_name);
+            repo.config = String::from(config);
+        }))
+    }
+
+    fn delete_repo(&self, id: i64) -> Res<()> {
+        self.write(|data| update_one(&mut data.repos, id, Error::NoSuchRepo, |repo| repo.is_delete = true))
+    }
+
+    fn select_all_repo(&self) -> Res<Vec<RepoStorage>> {
+        self.read(|data| Ok(data.repos.values().filter(|repo| !repo.is_delete).cloned().collect()))
+    }
+}
+
+// what the sql of select_sorted does, the whole query sorted and cut after the position it starts from
+fn sorted(data: &MemoryData, query: &SortQuery<'_>) -> Vec<SortValueStorage> {
+    let ids: Option<HashSet<i64>> = query.ids.map(|ids| ids.iter().copied().collect());
+    let mut rows: Vec<SortValueStorage> = data.items.range(query.start_id..query.end_id.max(query.start_id)).map(|(_, item)| item)
+        .filter(|item| item.repo_id == query.repo_id && !item.is_deleted)
+        .filter(|item| ids.as_ref().is_none_or(|ids| ids.contains(&item.id)))
+        .map(|item| SortValueStorage { item_id: item.id, value: sort_value(data, item, query.column) })
+        .collect();
+    let compare = |lhs: &SortValueStorage, rhs: &SortValueStorage| {
+        let order = match (lhs.value, rhs.value) {
+            (Some(l), Some(r)) => l.total_cmp(&r).then(lhs.item_id.cmp(&rhs.item_id)),
+            (Some(_), None) => return CmpOrdering::Less,
+            (None, Some(_)) => return CmpOrdering::Greater,
+            (None, None) => lhs.item_id.cmp(&rhs.item_id),
+        };
+        if query.descending { order.reverse() } else { order }
+    };
+    rows.sort_by(compare);
+    if let Some((value, item_id)) = query.after {
+        let last = SortValueStorage { item_id, value };
+        rows.retain(|row| compare(row, &last) == CmpOrdering::Greater);
+    }
+    rows
+}
+
+fn sort_value(data: &MemoryData, item: &ItemStorage, column: SortColumn) -> Option<f64> {
+    let extend = serde_json::from_str::<serde_json::Value>(&item.extend).unwrap_or_default();
+    let image = |field: &str| extend.pointer(&format!("/Picture/image_extend/{}", field))
+        .or_else(|| extend.pointer(&format!("/Photo/image_extend/{}", field)))
+        .and_then(|v| v.as_i64());
+    let size = image("w").zip(image("h")).filter(|(_, h)| *h > 0);
+    match column {
+        SortColumn::Size => Some(item.size as f64),
+        SortColumn::Area => size.map(|(w, h)| (w * h) as f64),
+        SortColumn::AspectRatio => size.map(|(w, h)| w as f64 / h as f64),
+        SortColumn::TakenAt => extend.pointer("/Photo/exif/taken_at").and_then(|v| v.as_str())
+            .and_then(|t| t.parse::<NaiveDateTime>().ok())
+            .map(|t| t.and_utc().timestamp() as f64),
+        SortColumn::TagCount => Some(data.relations.values()
+            .filter(|relation| relation.item_id == item.id && !relation.is_delete)
+            .filter(|relation| data.tags.get(&relation.tag_id).is_some_and(|tag| !tag.is_delete))
+            .count() as f64),
+        SortColumn::Random(seed) => Some(shuffle(seed, item.id) as f64),
+    }
+}
@@ -0,0 +1,37 @@
+use crate::common::{Error, Res};
+use log::info;
+use rusqlite::{params, Connection};
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+}
+
+// append only, never edit a migration that has been released
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "init", sql: include_str!("sql/0001_init.sql") },
+    Migration { version: 2, name: "search", sql: include_str!("sql/0002_search.sql") },
+    Migration { version: 3, name: "content_hash", sql: include_str!("sql/0003_content_hash.sql") },
+    Migration { version: 4, name: "geo", sql: include_str!("sql/0004_geo.sql") },
+    Migration { version: 5, name: "phash", sql: include_str!("sql/0005_phash.sql") },
+    Migration { version: 6, name: "trash", sql: include_str!("sql/0006_trash.sql") },
+];
+
+pub(super) fn migrate(connection: &mut Connection) -> Res<()> {
+    connection.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at DATETIME NOT NULL)")
+        .map_err(Error::SqliteError)?;
+    let current: i64 = connection.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", params![], |row| row.get(0))
+        .map_err(Error::SqliteError)?;
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        let transaction = connection.transaction().map_err(Error::SqliteError)?;
+        transaction.execute_batch(migration.sql).map_err(Error::SqliteError)?;
+        transaction.execute("INSERT INTO schema_version (version, name, applied_at) VALUES (?, ?, DATETIME('NOW'))",
+                            params![migration.version, migration.name])
+            .map_err(Error::SqliteError)?;
+        transaction.commit().map_err(Error::SqliteError)?;
+        info!("schema migrated to version {} ({})", migration.version, migration.name);
+    }
+    Ok(())
+}
@@ -1,13 +1,24 @@
 mod holder;
-pub(in crate::core) mod user;
-pub(in crate::core) mod item;
-pub(in crate::core) mod tag;
-pub(in crate::core) mod repo;
-pub(in crate::core) mod item_tag_relation;
-pub(in crate::core) mod user_repo_role;
+mod migration;
+mod store;
+mod sqlite;
+#[cfg(test)]
+mod memory;
+mod user;
+mod item;
+mod tag;
+mod repo;
+mod item_tag_relation;
+mod user_repo_role;
 
+pub(in crate::core) use holder::Database;
+pub(in crate::core) use store::{ItemStore, RepoStore, Storage, TagStore, UnitOfWork, UserStore};
+pub(in crate::core) use sqlite::SqliteStore;
+#[cfg(test)]
+pub(in crate::core) use memory::MemoryStore;
 pub(in crate::core) use user::UserStorage;
-pub(in crate::core) use item::ItemStorage;
+pub(in crate::core) use item::{GeoStorage, ItemStorage, NameStorage, PhashStorage, SortColumn, SortQuery, SortValueStorage};
 pub(in crate::core) use tag::TagStorage;
+pub(in crate::core) use repo::RepoStorage;
 pub(in crate::core) use item_tag_relation::ItemTagRelation;
 pub(in crate::core) use user_repo_role::UserRepoRoleStorage;
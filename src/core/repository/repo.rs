@@ -1,7 +1,8 @@
 use crate::common::{Error, Res};
-use crate::core::repository::holder::{exec, insert, query_all, update_check, RowData};
+use crate::core::repository::holder::{exec, insert, query_all, update_check, Database, RowData};
 use rusqlite::params;
 
+#[derive(Clone)]
 pub struct RepoStorage {
     pub id: i64,
     pub name: String,
@@ -9,20 +10,20 @@ pub struct RepoStorage {
     pub is_delete: bool,
 }
 
-pub fn create_repo(repo: &RepoStorage) -> Res<i64> {
-    insert("INSERT INTO repo (name, config, is_delete) VALUES (?, ?, false)", params![repo.name, repo.config])
+pub fn create_repo(db: &Database, repo: &RepoStorage) -> Res<i64> {
+    insert(db, "INSERT INTO repo (name, config, is_delete) VALUES (?, ?, false)", params![repo.name, repo.config])
 }
 
-pub fn update_repo(id: i64, new_name: &str, config: &str) -> Res<()> {
-    update_check(exec("UPDATE repo SET name = ?, config = ? WHERE id = ?", params![new_name, config, id]), Error::NoSuchRepo)
+pub fn update_repo(db: &Database, id: i64, new_name: &str, config: &str) -> Res<()> {
+    update_check(exec(db, "UPDATE repo SET name = ?, config = ? WHERE id = ?", params![new_name, config, id]), Error::NoSuchRepo)
 }
 
-pub fn delete_repo(id: i64) -> Res<()> {
-    update_check(exec("UPDATE repo SET is_delete = true WHERE id = ?", params![id]), Error::NoSuchRepo)
+pub fn delete_repo(db: &Database, id: i64) -> Res<()> {
+    update_check(exec(db, "UPDATE repo SET is_delete = true WHERE id = ?", params![id]), Error::NoSuchRepo)
 }
 
-pub fn select_all_repo() -> Res<Vec<RepoStorage>> {
-    query_all("SELECT * FROM repo WHERE is_delete = false", params![], map)
+pub fn select_all_repo(db: &Database) -> Res<Vec<RepoStorage>> {
+    query_all(db, "SELECT * FROM repo WHERE is_delete = false", params![], map)
 }
 
 fn map(row: &RowData<'_>) -> Res<RepoStorage> {
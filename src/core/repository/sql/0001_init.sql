@@ -0,0 +1,61 @@
+CREATE TABLE IF NOT EXISTS users (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    name        TEXT    NOT NULL UNIQUE,
+    password    TEXT    NOT NULL,
+    token       TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_users_token ON users (token);
+
+CREATE TABLE IF NOT EXISTS repo (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    name        TEXT    NOT NULL,
+    config      TEXT    NOT NULL,
+    is_delete   BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE TABLE IF NOT EXISTS items (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    name        TEXT    NOT NULL,
+    ext         INTEGER NOT NULL,
+    size        INTEGER NOT NULL,
+    created_at  DATETIME NOT NULL,
+    is_deleted  BOOLEAN NOT NULL DEFAULT false,
+    repo_id     INTEGER NOT NULL,
+    path        TEXT    NOT NULL,
+    extend      TEXT    NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_items_repo_id ON items (repo_id, is_deleted, id);
+CREATE INDEX IF NOT EXISTS idx_items_created_at ON items (repo_id, created_at);
+
+CREATE TABLE IF NOT EXISTS tags (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    name        TEXT    NOT NULL,
+    repo_id     INTEGER NOT NULL,
+    parent      INTEGER NOT NULL,
+    creator     INTEGER NOT NULL,
+    is_delete   BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE INDEX IF NOT EXISTS idx_tags_repo_id ON tags (repo_id);
+
+CREATE TABLE IF NOT EXISTS item_tag_relation (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    tag_id      INTEGER NOT NULL,
+    item_id     INTEGER NOT NULL,
+    creator     INTEGER NOT NULL,
+    is_delete   BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE INDEX IF NOT EXISTS idx_item_tag_relation_tag_id ON item_tag_relation (tag_id);
+CREATE INDEX IF NOT EXISTS idx_item_tag_relation_item_id ON item_tag_relation (item_id);
+
+CREATE TABLE IF NOT EXISTS user_repo_role (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id     INTEGER NOT NULL,
+    repo_id     INTEGER NOT NULL,
+    role        INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_repo_role_user_id ON user_repo_role (user_id);
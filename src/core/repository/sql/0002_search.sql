@@ -0,0 +1,57 @@
+ALTER TABLE items ADD COLUMN caption TEXT;
+
+-- rowid is the item id, tags holds the names of every live tag on the item
+CREATE VIRTUAL TABLE IF NOT EXISTS item_search USING fts5 (
+    name,
+    caption,
+    tags,
+    tokenize = 'trigram'
+);
+
+INSERT INTO item_search (item_search, rank) VALUES ('rank', 'bm25(10.0, 5.0, 2.0)');
+
+INSERT INTO item_search (rowid, name, caption, tags)
+SELECT id, name, caption, (
+    SELECT GROUP_CONCAT(t.name, ' ') FROM item_tag_relation r JOIN tags t ON t.id = r.tag_id
+    WHERE r.item_id = items.id AND r.is_delete = false AND t.is_delete = false
+) FROM items;
+
+CREATE TRIGGER IF NOT EXISTS trg_items_search_insert AFTER INSERT ON items BEGIN
+    INSERT INTO item_search (rowid, name, caption, tags) VALUES (new.id, new.name, new.caption, NULL);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_items_search_update AFTER UPDATE OF name, caption ON items BEGIN
+    UPDATE item_search SET name = new.name, caption = new.caption WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_items_search_delete AFTER DELETE ON items BEGIN
+    DELETE FROM item_search WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_relation_search_insert AFTER INSERT ON item_tag_relation BEGIN
+    UPDATE item_search SET tags = (
+        SELECT GROUP_CONCAT(t.name, ' ') FROM item_tag_relation r JOIN tags t ON t.id = r.tag_id
+        WHERE r.item_id = new.item_id AND r.is_delete = false AND t.is_delete = false
+    ) WHERE rowid = new.item_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_relation_search_update AFTER UPDATE OF is_delete ON item_tag_relation BEGIN
+    UPDATE item_search SET tags = (
+        SELECT GROUP_CONCAT(t.name, ' ') FROM item_tag_relation r JOIN tags t ON t.id = r.tag_id
+        WHERE r.item_id = new.item_id AND r.is_delete = false AND t.is_delete = false
+    ) WHERE rowid = new.item_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_relation_search_delete AFTER DELETE ON item_tag_relation BEGIN
+    UPDATE item_search SET tags = (
+        SELECT GROUP_CONCAT(t.name, ' ') FROM item_tag_relation r JOIN tags t ON t.id = r.tag_id
+        WHERE r.item_id = old.item_id AND r.is_delete = false AND t.is_delete = false
+    ) WHERE rowid = old.item_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_tags_search_update AFTER UPDATE OF name, is_delete ON tags BEGIN
+    UPDATE item_search SET tags = (
+        SELECT GROUP_CONCAT(t.name, ' ') FROM item_tag_relation r JOIN tags t ON t.id = r.tag_id
+        WHERE r.item_id = item_search.rowid AND r.is_delete = false AND t.is_delete = false
+    ) WHERE rowid IN (SELECT item_id FROM item_tag_relation WHERE tag_id = new.id);
+END;
@@ -0,0 +1,4 @@
+-- sha256 of the stored bytes in lowercase hex, NULL for items uploaded before hashing
+ALTER TABLE items ADD COLUMN hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_items_hash ON items (repo_id, hash);
@@ -0,0 +1,31 @@
+-- gps of the photos, copied out of the extend json so it can be searched by range
+CREATE TABLE IF NOT EXISTS item_geo (
+    item_id   INTEGER PRIMARY KEY,
+    repo_id   INTEGER NOT NULL,
+    latitude  REAL    NOT NULL,
+    longitude REAL    NOT NULL,
+    altitude  REAL
+);
+
+CREATE INDEX IF NOT EXISTS idx_item_geo_location ON item_geo (repo_id, latitude, longitude);
+
+INSERT INTO item_geo (item_id, repo_id, latitude, longitude, altitude)
+SELECT id, repo_id, json_extract(extend, '$.Photo.exif.gps.latitude'), json_extract(extend, '$.Photo.exif.gps.longitude'), json_extract(extend, '$.Photo.exif.gps.altitude')
+FROM items WHERE CASE WHEN json_valid(extend) THEN json_extract(extend, '$.Photo.exif.gps.latitude') END IS NOT NULL;
+
+CREATE TRIGGER IF NOT EXISTS trg_items_geo_insert AFTER INSERT ON items
+WHEN json_extract(new.extend, '$.Photo.exif.gps.latitude') IS NOT NULL BEGIN
+    INSERT INTO item_geo (item_id, repo_id, latitude, longitude, altitude)
+    VALUES (new.id, new.repo_id, json_extract(new.extend, '$.Photo.exif.gps.latitude'), json_extract(new.extend, '$.Photo.exif.gps.longitude'), json_extract(new.extend, '$.Photo.exif.gps.altitude'));
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_items_geo_update AFTER UPDATE OF extend, repo_id ON items BEGIN
+    DELETE FROM item_geo WHERE item_id = old.id;
+    INSERT INTO item_geo (item_id, repo_id, latitude, longitude, altitude)
+    SELECT new.id, new.repo_id, json_extract(new.extend, '$.Photo.exif.gps.latitude'), json_extract(new.extend, '$.Photo.exif.gps.longitude'), json_extract(new.extend, '$.Photo.exif.gps.altitude')
+    WHERE json_extract(new.extend, '$.Photo.exif.gps.latitude') IS NOT NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_items_geo_delete AFTER DELETE ON items BEGIN
+    DELETE FROM item_geo WHERE item_id = old.id;
+END;
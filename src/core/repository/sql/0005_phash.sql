@@ -0,0 +1,29 @@
+-- perceptual hash of the images, copied out of the extend json so a repo is read in one pass
+CREATE TABLE IF NOT EXISTS item_phash (
+    item_id INTEGER PRIMARY KEY,
+    repo_id INTEGER NOT NULL,
+    phash   TEXT    NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_item_phash_repo ON item_phash (repo_id);
+
+INSERT INTO item_phash (item_id, repo_id, phash)
+SELECT id, repo_id, COALESCE(json_extract(extend, '$.Picture.image_extend.phash'), json_extract(extend, '$.Photo.image_extend.phash'))
+FROM items WHERE CASE WHEN json_valid(extend) THEN COALESCE(json_extract(extend, '$.Picture.image_extend.phash'), json_extract(extend, '$.Photo.image_extend.phash')) END IS NOT NULL;
+
+CREATE TRIGGER IF NOT EXISTS trg_items_phash_insert AFTER INSERT ON items
+WHEN COALESCE(json_extract(new.extend, '$.Picture.image_extend.phash'), json_extract(new.extend, '$.Photo.image_extend.phash')) IS NOT NULL BEGIN
+    INSERT INTO item_phash (item_id, repo_id, phash)
+    VALUES (new.id, new.repo_id, COALESCE(json_extract(new.extend, '$.Picture.image_extend.phash'), json_extract(new.extend, '$.Photo.image_extend.phash')));
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_items_phash_update AFTER UPDATE OF extend, repo_id ON items BEGIN
+    DELETE FROM item_phash WHERE item_id = old.id;
+    INSERT INTO item_phash (item_id, repo_id, phash)
+    SELECT new.id, new.repo_id, COALESCE(json_extract(new.extend, '$.Picture.image_extend.phash'), json_extract(new.extend, '$.Photo.image_extend.phash'))
+    WHERE COALESCE(json_extract(new.extend, '$.Picture.image_extend.phash'), json_extract(new.extend, '$.Photo.image_extend.phash')) IS NOT NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_items_phash_delete AFTER DELETE ON items BEGIN
+    DELETE FROM item_phash WHERE item_id = old.id;
+END;
@@ -0,0 +1,6 @@
+-- when the item went to the trash, NULL while it is live, the retention purge counts from it
+ALTER TABLE items ADD COLUMN deleted_at DATETIME;
+
+UPDATE items SET deleted_at = DATETIME('NOW') WHERE is_deleted = true;
+
+CREATE INDEX IF NOT EXISTS idx_items_deleted_at ON items (repo_id, is_deleted, deleted_at);
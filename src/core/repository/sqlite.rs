@@ -0,0 +1,271 @@
+use crate::common::Res;
+use crate::core::repository::holder::Transaction;
+use crate::core::repository::store::{ItemStore, RepoStore, TagStore, TransactionHandle, UnitOfWork, UserStore};
+use crate::core::repository::{item, item_tag_relation, repo, tag, user, user_repo_role};
+use crate::core::repository::{Database, GeoStorage, ItemStorage, NameStorage, PhashStorage, SortQuery, SortValueStorage, ItemTagRelation, RepoStorage, TagStorage, UserRepoRoleStorage, UserStorage};
+
+pub struct SqliteStore {
+    db: Database,
+}
+
+impl SqliteStore {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+impl TransactionHandle for Transaction {
+    fn commit(self: Box<Self>) -> Res<()> {
+        Transaction::commit(*self)
+    }
+
+    fn rollback(self: Box<Self>) -> Res<()> {
+        Transaction::rollback(*self)
+    }
+}
+
+impl UnitOfWork for SqliteStore {
+    fn begin(&self) -> Res<Box<dyn TransactionHandle>> {
+        Ok(Box::new(self.db.begin()?))
+    }
+}
+
+impl ItemStore for SqliteStore {
+    fn create(&self, item: &ItemStorage) -> Res<i64> {
+        item::create(&self.db, item)
+    }
+
+    fn import(&self, item: &ItemStorage) -> Res<i64> {
+        item::import(&self.db, item)
+    }
+
+    fn select_by_id(&self, id: i64) -> Res<ItemStorage> {
+        item::select_by_id(&self.db, id)
+    }
+
+    fn select_start_time(&self, repo_id: i64, start_time: i64) -> Res<i64> {
+        item::select_start_time(&self.db, repo_id, start_time)
+    }
+
+    fn select_end_time(&self, repo_id: i64, end_time: i64) -> Res<i64> {
+        item::select_end_time(&self.db, repo_id, end_time)
+    }
+
+    fn select_min_max_id(&self, repo_id: i64) -> Res<(i64, i64)> {
+        item::select_min_max_id(&self.db, repo_id)
+    }
+
+    fn select_by_ids(&self, ids: &Vec<i64>) -> Res<Vec<ItemStorage>> {
+        item::select_item_by_ids(&self.db, ids)
+    }
+
+    fn select_by_hash(&self, repo_id: i64, hash: &str) -> Res<Vec<ItemStorage>> {
+        item::select_by_hash(&self.db, repo_id, hash)
+    }
+
+    fn select_from(&self, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+        item::select_from(&self.db, repo_id, start_id, end_id, limit)
+    }
+
+    fn select_to(&self, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+        item::select_to(&self.db, repo_id, start_id, end_id, limit)
+    }
+
+    fn count_range(&self, repo_id: i64, start_id: i64, end_id: i64) -> Res<i64> {
+        item::count_range(&self.db, repo_id, start_id, end_id)
+    }
+
+    fn mark_delete(&self, id: i64) -> Res<()> {
+        item::mark_delete_item(&self.db, id)
+    }
+
+    fn update(&self, item: &ItemStorage) -> Res<()> {
+        item::update_item(&self.db, item)
+    }
+
+    fn change_path(&self, id: i64, path: &str) -> Res<()> {
+        item::change_path(&self.db, id, path)
+    }
+
+    fn change_repo(&self, id: i64, repo_id: i64) -> Res<()> {
+        item::change_repo(&self.db, id, repo_id)
+    }
+
+    fn reset(&self, id: i64) -> Res<()> {
+        item::reset_item(&self.db, id)
+    }
+
+    fn purge(&self, id: i64) -> Res<()> {
+        item::purge_item(&self.db, id)
+    }
+
+    fn select_deleted(&self, repo_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+        item::select_deleted(&self.db, repo_id, end_id, limit)
+    }
+
+    fn select_expired(&self, repo_id: i64, deleted_before: i64, limit: i64) -> Res<Vec<ItemStorage>> {
+        item::select_expired(&self.db, repo_id, deleted_before, limit)
+    }
+
+    fn count_by_path(&self, repo_id: i64, path: &str) -> Res<usize> {
+        item::count_by_path(&self.db, repo_id, path)
+    }
+
+    fn search(&self, repo_id: i64, keyword: &str) -> Res<Vec<i64>> {
+        item::search(&self.db, repo_id, keyword)
+    }
+
+    fn select_geo(&self, repo_id: i64, min_lat: f64, max_lat: f64, min_lng: f64, max_lng: f64) -> Res<Vec<GeoStorage>> {
+        item::select_geo(&self.db, repo_id, min_lat, max_lat, min_lng, max_lng)
+    }
+
+    fn select_phash(&self, repo_id: i64) -> Res<Vec<PhashStorage>> {
+        item::select_phash(&self.db, repo_id)
+    }
+
+    fn select_names(&self, repo_id: i64, start_id: i64, end_id: i64) -> Res<Vec<NameStorage>> {
+        item::select_names(&self.db, repo_id, start_id, end_id)
+    }
+
+    fn select_names_by_ids(&self, ids: &[i64]) -> Res<Vec<NameStorage>> {
+        item::select_names_by_ids(&self.db, &ids.to_vec())
+    }
+
+    fn select_sorted(&self, query: &SortQuery<'_>, limit: i64) -> Res<Vec<SortValueStorage>> {
+        item::select_sorted(&self.db, query, limit)
+    }
+
+    fn count_sorted(&self, query: &SortQuery<'_>) -> Res<i64> {
+        item::count_sorted(&self.db, query)
+    }
+}
+
+impl TagStore for SqliteStore {
+    fn create_tag(&self, tag: &TagStorage) -> Res<i64> {
+        tag::create_tag(&self.db, tag)
+    }
+
+    fn select_by_id(&self, id: i64) -> Res<TagStorage> {
+        tag::select_by_id(&self.db, id)
+    }
+
+    fn select_all(&self, repo_id: i64) -> Res<Vec<TagStorage>> {
+        tag::select_all(&self.db, repo_id)
+    }
+
+    fn select_by_ids(&self, ids: &Vec<i64>) -> Res<Vec<TagStorage>> {
+        tag::select_by_ids(&self.db, ids)
+    }
+
+    fn update_tag(&self, tag: &TagStorage) -> Res<()> {
+        tag::update_tag(&self.db, tag)
+    }
+
+    fn delete_tag(&self, id: i64) -> Res<()> {
+        tag::delete_tag(&self.db, id)
+    }
+
+    fn reset_tag(&self, id: i64) -> Res<()> {
+        tag::rest_tag(&self.db, id)
+    }
+
+    fn create_relation(&self, relation: &ItemTagRelation) -> Res<i64> {
+        item_tag_relation::create(&self.db, relation)
+    }
+
+    fn delete_relation(&self, id: i64) -> Res<()> {
+        item_tag_relation::delete(&self.db, id)
+    }
+
+    fn delete_relation_by_tag(&self, tag_id: i64) -> Res<usize> {
+        item_tag_relation::delete_tag(&self.db, tag_id)
+    }
+
+    fn delete_relation_by_item(&self, item_id: i64) -> Res<usize> {
+        item_tag_relation::delete_item(&self.db, item_id)
+    }
+
+    fn purge_relation_by_item(&self, item_id: i64) -> Res<usize> {
+        item_tag_relation::purge_item(&self.db, item_id)
+    }
+
+    fn revert_relation_by_tag(&self, tag_id: i64) -> Res<usize> {
+        item_tag_relation::revert_all(&self.db, tag_id)
+    }
+
+    fn select_relation_by_tags(&self, tags: &Vec<i64>) -> Res<Vec<ItemTagRelation>> {
+        item_tag_relation::select_by_tags(&self.db, tags)
+    }
+
+    fn select_relation_by_item(&self, item_id: i64) -> Res<Vec<ItemTagRelation>> {
+        item_tag_relation::select_by_item(&self.db, item_id)
+    }
+
+    fn select_relation_by_items(&self, items: &Vec<i64>) -> Res<Vec<ItemTagRelation>> {
+        item_tag_relation::select_by_items(&self.db, items)
+    }
+
+    fn select_relation_by_both(&self, item_id: i64, tag_id: i64) -> Res<ItemTagRelation> {
+        item_tag_relation::select_by_both(&self.db, item_id, tag_id)
+    }
+}
+
+impl UserStore for SqliteStore {
+    fn create_user(&self, user: &UserStorage) -> Res<i64> {
+        user::create_user(&self.db, user)
+    }
+
+    fn query_by_id(&self, id: i64) -> Res<UserStorage> {
+        user::query_by_id(&self.db, id)
+    }
+
+    fn query_by_name(&self, name: &str) -> Res<UserStorage> {
+        user::query_by_name(&self.db, name)
+    }
+
+    fn query_by_token(&self, token: &str) -> Res<UserStorage> {
+        user::query_by_token(&self.db, token)
+    }
+
+    fn update_token(&self, id: i64, token: &str) -> Res<()> {
+        user::update_token(&self.db, id, token)
+    }
+
+    fn update_password(&self, id: i64, password: &str) -> Res<()> {
+        user::update_password(&self.db, id, password)
+    }
+
+    fn clear_token(&self, id: i64) -> Res<()> {
+        user::clear_token(&self.db, id)
+    }
+
+    fn create_user_repo_role(&self, role: &UserRepoRoleStorage) -> Res<i64> {
+        user_repo_role::create_user_repo_role(&self.db, role)
+    }
+
+    fn select_user_role(&self, user_id: i64) -> Res<Vec<UserRepoRoleStorage>> {
+        user_repo_role::select_user_role(&self.db, user_id)
+    }
+
+    fn update_user_role(&self, id: i64, role: i64) -> Res<()> {
+        user_repo_role::update_user_role(&self.db, id, role)
+    }
+}
+
+impl RepoStore for SqliteStore {
+    fn create_repo(&self, repo: &RepoStorage) -> Res<i64> {
+        repo::create_repo(&self.db, repo)
+    }
+
+    fn update_repo(&self, id: i64, new_name: &str, config: &str) -> Res<()> {
+        repo::update_repo(&self.db, id, new_name, config)
+    }
+
+    fn delete_repo(&self, id: i64) -> Res<()> {
+        repo::delete_repo(&self.db, id)
+    }
+
+    fn select_all_repo(&self) -> Res<Vec<RepoStorage>> {
+        repo::select_all_repo(&self.db)
+    }
+}
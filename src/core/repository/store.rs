@@ -0,0 +1,197 @@
+use crate::common::Res;
+use crate::core::repository::{Database, GeoStorage, ItemStorage, NameStorage, PhashStorage, SortQuery, SortValueStorage, ItemTagRelation, RepoStorage, SqliteStore, TagStorage, UserRepoRoleStorage, UserStorage};
+use std::sync::Arc;
+
+pub trait TransactionHandle {
+    fn commit(self: Box<Self>) -> Res<()>;
+
+    fn rollback(self: Box<Self>) -> Res<()>;
+}
+
+pub trait UnitOfWork: Send + Sync {
+    // nested calls join the outermost transaction, dropping the handle without commit rolls it back
+    fn begin(&self) -> Res<Box<dyn TransactionHandle>>;
+}
+
+pub trait ItemStore: Send + Sync {
+    fn create(&self, item: &ItemStorage) -> Res<i64>;
+
+    fn import(&self, item: &ItemStorage) -> Res<i64>;
+
+    fn select_by_id(&self, id: i64) -> Res<ItemStorage>;
+
+    fn select_start_time(&self, repo_id: i64, start_time: i64) -> Res<i64>;
+
+    fn select_end_time(&self, repo_id: i64, end_time: i64) -> Res<i64>;
+
+    fn select_min_max_id(&self, repo_id: i64) -> Res<(i64, i64)>;
+
+    fn select_by_ids(&self, ids: &Vec<i64>) -> Res<Vec<ItemStorage>>;
+
+    fn select_by_hash(&self, repo_id: i64, hash: &str) -> Res<Vec<ItemStorage>>;
+
+    fn select_from(&self, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>>;
+
+    fn select_to(&self, repo_id: i64, start_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>>;
+
+    // live items of the repo with start_id <= id < end_id
+    fn count_range(&self, repo_id: i64, start_id: i64, end_id: i64) -> Res<i64>;
+
+    fn mark_delete(&self, id: i64) -> Res<()>;
+
+    fn update(&self, item: &ItemStorage) -> Res<()>;
+
+    fn change_path(&self, id: i64, path: &str) -> Res<()>;
+
+    fn change_repo(&self, id: i64, repo_id: i64) -> Res<()>;
+
+    fn reset(&self, id: i64) -> Res<()>;
+
+    fn purge(&self, id: i64) -> Res<()>;
+
+    // items in the trash with id below end_id, newest first
+    fn select_deleted(&self, repo_id: i64, end_id: i64, limit: i64) -> Res<Vec<ItemStorage>>;
+
+    // items in the trash since before the timestamp
+    fn select_expired(&self, repo_id: i64, deleted_before: i64, limit: i64) -> Res<Vec<ItemStorage>>;
+
+    // items of the repo stored at the path
+    fn count_by_path(&self, repo_id: i64, path: &str) -> Res<usize>;
+
+    // ids of the live items matching every term of the keyword, best match first
+    fn search(&self, repo_id: i64, keyword: &str) -> Res<Vec<i64>>;
+
+    // gps of the live items inside the box, min_lng > max_lng crosses the antimeridian
+    fn select_geo(&self, repo_id: i64, min_lat: f64, max_lat: f64, min_lng: f64, max_lng: f64) -> Res<Vec<GeoStorage>>;
+
+    // perceptual hashes of the live images of the repo, by id
+    fn select_phash(&self, repo_id: i64) -> Res<Vec<PhashStorage>>;
+
+    // names of the live items of the repo with start_id <= id < end_id, in no particular order
+    fn select_names(&self, repo_id: i64, start_id: i64, end_id: i64) -> Res<Vec<NameStorage>>;
+
+    // names of the live items among ids, in no particular order
+    fn select_names_by_ids(&self, ids: &[i64]) -> Res<Vec<NameStorage>>;
+
+    // at most limit items of the query with the value they are ordered by
+    fn select_sorted(&self, query: &SortQuery<'_>, limit: i64) -> Res<Vec<SortValueStorage>>;
+
+    // items of the query, after the position it starts from
+    fn count_sorted(&self, query: &SortQuery<'_>) -> Res<i64>;
+}
+
+pub trait TagStore: Send + Sync {
+    // region tag
+
+    fn create_tag(&self, tag: &TagStorage) -> Res<i64>;
+
+    fn select_by_id(&self, id: i64) -> Res<TagStorage>;
+
+    fn select_all(&self, repo_id: i64) -> Res<Vec<TagStorage>>;
+
+    fn select_by_ids(&self, ids: &Vec<i64>) -> Res<Vec<TagStorage>>;
+
+    fn update_tag(&self, tag: &TagStorage) -> Res<()>;
+
+    fn delete_tag(&self, id: i64) -> Res<()>;
+
+    fn reset_tag(&self, id: i64) -> Res<()>;
+
+    // endregion
+
+    // region item tag relation
+
+    fn create_relation(&self, relation: &ItemTagRelation) -> Res<i64>;
+
+    fn delete_relation(&self, id: i64) -> Res<()>;
+
+    fn delete_relation_by_tag(&self, tag_id: i64) -> Res<usize>;
+
+    fn delete_relation_by_item(&self, item_id: i64) -> Res<usize>;
+
+    fn purge_relation_by_item(&self, item_id: i64) -> Res<usize>;
+
+    fn revert_relation_by_tag(&self, tag_id: i64) -> Res<usize>;
+
+    fn select_relation_by_tags(&self, tags: &Vec<i64>) -> Res<Vec<ItemTagRelation>>;
+
+    fn select_relation_by_item(&self, item_id: i64) -> Res<Vec<ItemTagRelation>>;
+
+    fn select_relation_by_items(&self, items: &Vec<i64>) -> Res<Vec<ItemTagRelation>>;
+
+    fn select_relation_by_both(&self, item_id: i64, tag_id: i64) -> Res<ItemTagRelation>;
+
+    // endregion
+}
+
+pub trait UserStore: Send + Sync {
+    fn create_user(&self, user: &UserStorage) -> Res<i64>;
+
+    fn query_by_id(&self, id: i64) -> Res<UserStorage>;
+
+    fn query_by_name(&self, name: &str) -> Res<UserStorage>;
+
+    fn query_by_token(&self, token: &str) -> Res<UserStorage>;
+
+    fn update_token(&self, id: i64, token: &str) -> Res<()>;
+
+    fn update_password(&self, id: i64, password: &str) -> Res<()>;
+
+    fn clear_token(&self, id: i64) -> Res<()>;
+
+    fn create_user_repo_role(&self, role: &UserRepoRoleStorage) -> Res<i64>;
+
+    fn select_user_role(&self, user_id: i64) -> Res<Vec<UserRepoRoleStorage>>;
+
+    fn update_user_role(&self, id: i64, role: i64) -> Res<()>;
+}
+
+pub trait RepoStore: Send + Sync {
+    fn create_repo(&self, repo: &RepoStorage) -> Res<i64>;
+
+    fn update_repo(&self, id: i64, new_name: &str, config: &str) -> Res<()>;
+
+    fn delete_repo(&self, id: i64) -> Res<()>;
+
+    fn select_all_repo(&self) -> Res<Vec<RepoStorage>>;
+}
+
+pub struct Storage {
+    pub unit: Arc<dyn UnitOfWork>,
+    pub item: Arc<dyn ItemStore>,
+    pub tag: Arc<dyn TagStore>,
+    pub user: Arc<dyn UserStore>,
+    pub repo: Arc<dyn RepoStore>,
+}
+
+impl Storage {
+    pub fn sqlite(database: Database) -> Self {
+        let store = Arc::new(SqliteStore::new(database));
+        Self { unit: store.clone(), item: store.clone(), tag: store.clone(), user: store.clone(), repo: store }
+    }
+
+    #[cfg(test)]
+    pub fn memory() -> Self {
+        let store = Arc::new(crate::core::repository::MemoryStore::new());
+        Self { unit: store.clone(), item: store.clone(), tag: store.clone(), user: store.clone(), repo: store }
+    }
+}
+
+impl dyn UnitOfWork {
+    pub fn transaction<F, R>(&self, f: F) -> Res<R>
+    where
+        F: FnOnce() -> Res<R>,
+    {
+        let transaction = self.begin()?;
+        match f() {
+            Ok(res) => {
+                transaction.commit()?;
+                Ok(res)
+            }
+            Err(e) => {
+                transaction.rollback()?;
+                Err(e)
+            }
+        }
+    }
+}
@@ -1,29 +1,174 @@
-use crate::common::file::FileStream;
-use crate::common::{build_thumbnail, build_thumbnail_from_file, file_check, get_size, ContentType, DirNode, Error, FileNode, FileType, Node, Res};
+use crate::common::bktree::BkTree;
+use crate::common::file::content_type;
+use crate::common::geo::normalize;
+use crate::common::{build_animated_thumbnail_from_file, build_frame_from_file, build_rendition_from_file, build_thumbnail_from_file, can_decode, dominant_colors, file_check, get_size, json, load_file, perceptual_hash, placeholder, read_exif, ContentHasher, ContentType, DirNode, Error, FileNode, FileType, Fit, FrameInfo, Node, RenditionFormat, Res};
 use crate::core::manager::tag::TagManager;
 use crate::core::manager::ItemExtend::{Empty, Photo, Picture};
-use crate::core::manager::{CommonConfig, Config, ImageExtend, Item, ItemExtend, ItemManager, PhotoExtend, PictureExtend, Repo, RepoConfig, RepoFileOrder, RepoManager, ResourceManager, UserRole};
-use crate::core::service::check_permission;
-use crate::core::service::item::condition::ItemCondition;
+use crate::core::manager::{CommonConfig, Config, DuplicateMode, ImageExtend, Item, ItemExtend, ItemManager, PhotoExtend, PictureExtend, Repo, RepoConfig, RepoFileOrder, RepoManager, RepoResourceManager, ResourceManager, RgbColor, UserRole};
+use crate::core::repository::UnitOfWork;
+use crate::core::service::{batch, check_permission, get_user_id, BatchResult};
+use crate::core::service::item::condition::{ItemCondition, ItemCursor, ItemOrder};
 use crate::core::service::item::filter::{ConditionContext, ItemFilter};
-use chrono::Datelike;
-use std::cmp::min;
-use std::sync::Arc;
+use chrono::{DateTime, Datelike, Utc};
+use log::error;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+// enough leading bytes for every magic number in content_type, the tar one sits at offset 257
+const UPLOAD_HEAD_SIZE: usize = 512;
+
+// dominant colors kept per image
+const PALETTE_SIZE: usize = 5;
+
+// bits two perceptual hashes may differ in and still be looked up, 64 would match everything
+const MAX_PHASH_DISTANCE: u32 = 16;
+
+// items one list page holds at most
+const MAX_LIST_SIZE: i64 = 100;
+
+// items a list page looks at before it stops filling itself, a filter that rarely matches would walk the whole repo
+const DEFAULT_LIST_SCAN: i64 = 1000;
+const MAX_LIST_SCAN: i64 = 10000;
+
+// cells per side of the map grid a cluster request may ask for
+const MAX_GEO_GRID: u32 = 256;
+
+// an upload session nobody has written to for this long is dropped together with its bytes
+const UPLOAD_SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// images the background thread decodes for one repo before it looks at the next one
+const BACKFILL_BATCH: i64 = 100;
 
 pub struct ItemService {
 
     max_thumbnail_size: usize,
+    // ids of the upload sessions a request is working on
+    busy_sessions: Arc<Mutex<HashSet<String>>>,
+    // repos the background thread reads the extends of again, with the id the next batch starts from
+    backfills: Arc<Mutex<HashMap<i64, i64>>>,
 
+    unit: Arc<dyn UnitOfWork>,
     repo: Arc<RepoManager>,
     resource: Arc<ResourceManager>,
     item: Arc<ItemManager>,
     tag: Arc<TagManager>,
 }
 
+// an upload streamed into the repo's .temp directory, the temp file is removed when it is dropped
+pub struct Upload {
+    repo: Repo,
+    file: FileNode,
+    head: Vec<u8>,
+    size: usize,
+    limit: usize,
+    hasher: ContentHasher,
+    // the bytes of an upload session stay staged when creating the item fails, so finishing can be retried
+    keep: bool,
+}
+
+// stored as <id>.json next to the staged bytes <id> in the repo's .temp directory
+#[derive(serde::Serialize, serde::Deserialize)]
+struct UploadSessionMeta {
+    repo_id: i64,
+    user_id: i64,
+    name: String,
+    caption: Option<String>,
+    size: usize,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    updated_at: DateTime<Utc>,
+}
+
+// how a list is walked, cursor is the one of the previous page
+pub struct ListOptions {
+    pub limit: i64,
+    pub from_big: bool,
+    pub order: ItemOrder,
+    // for the random order, a new one is drawn when there is none
+    pub seed: Option<u64>,
+    pub scan: Option<i64>,
+    pub cursor: Option<String>,
+    pub count: bool,
+}
+
+// cursor is none once the list is through
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ItemPage {
+    pub items: Vec<Item>,
+    pub cursor: Option<String>,
+    // items matching the conditions, filters are left out since counting them means a full scan
+    pub total: Option<i64>,
+    pub remaining: Option<i64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SimilarItem {
+    pub item: Item,
+    // differing bits of the perceptual hashes
+    pub distance: u32,
+}
+
+// the photos of one grid cell of the map, located at their mean position
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct GeoCluster {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub count: usize,
+    // the newest photo of the cell, for a preview
+    pub item_id: i64,
+}
+
+// next_id is none once the background thread is through the repo
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct BackfillProgress {
+    pub repo_id: i64,
+    pub next_id: Option<i64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct UploadSession {
+    pub id: String,
+    pub repo_id: i64,
+    pub size: usize,
+    // bytes already staged, the next chunk has to start here
+    pub offset: usize,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub expires_at: DateTime<Utc>,
+}
+
+// a stored file with what a client needs to cache it and to seek in it
+pub struct ItemContent {
+    pub file: FileNode,
+    pub content_type: &'static ContentType,
+    pub size: u64,
+    pub name: String,
+    pub etag: String,
+    pub modified: DateTime<Utc>,
+}
+
+pub struct UploadSessionWriter {
+    id: String,
+    meta: UploadSessionMeta,
+    file: FileNode,
+    offset: usize,
+    _lock: UploadSessionLock,
+}
+
+// taken while a request writes to or finishes a session, a second one on the same session is turned away
+struct UploadSessionLock {
+    id: String,
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
 impl ItemService {
     pub fn new(config: &Config) -> Self {
         Self {
             max_thumbnail_size: config.setting.max_thumbnail_size,
+            busy_sessions: Arc::new(Mutex::new(HashSet::new())),
+            backfills: Arc::new(Mutex::new(HashMap::new())),
+            unit: config.unit.clone(),
             repo: config.repo_manager.clone(),
             resource: config.resource_manager.clone(),
             item: config.item_manager.clone(),
@@ -31,34 +176,133 @@ impl ItemService {
         }
     }
 
-    pub fn create(&self, repo_id: i64, name: String, data: &Vec<u8>) -> Res<Item> {
+    pub fn begin_upload(&self, repo_id: i64) -> Res<Upload> {
         check_permission(repo_id, UserRole::Manager)?;
-        let file_type = file_check(data)?;
         let repo = self.repo.select_repo_by_id(repo_id)?;
-        let item = Item {
+        let resource = self.resource.get_or_init(&repo.name)?;
+        let file = resource.create_temp_file()?;
+        let limit = repo.config.common_config()?.max_upload_size;
+        Ok(Upload { repo, file, head: Vec::with_capacity(UPLOAD_HEAD_SIZE), size: 0, limit, hasher: ContentHasher::new(), keep: false })
+    }
+
+    // an upload is only handed out after the permission check, the context is not read again once the body is streamed
+    pub fn create(&self, mut upload: Upload, name: String, caption: Option<String>) -> Res<Item> {
+        let repo_id = upload.repo.id;
+        let file_type = file_check(&upload.head)?;
+        let hash = std::mem::take(&mut upload.hasher).finish();
+        let repo = &upload.repo;
+        let mut item = Item {
             id: 0,
             name,
             ext: file_type,
-            size: data.len(),
+            size: upload.size,
             created_at: Default::default(),
             is_deleted: false,
             repo_id,
             path: "".to_string(),
-            extend: Self::build_extend(&repo, data, file_type)?,
+            extend: Self::build_extend(repo, &upload.file, file_type)?,
+            caption,
+            hash: Some(hash.clone()),
+            deleted_at: None,
         };
 
-        let mut item = self.item.create(item)?;
-        let file = Self::build_repo_path(&repo, &item)?;
-        let path = file.absolute_path();
-        self.item.change_path(item.id, &path)?;
+        // the file is moved last, a failed move rolls back the item row together with its path
+        let mut moved = None;
+        let result = self.unit.transaction(|| {
+            if let Some(existing) = self.item.select_by_hash(repo_id, &hash)?.into_iter().next() {
+                match repo.config.common_config()?.duplicate {
+                    DuplicateMode::Allow => {}
+                    DuplicateMode::ReturnExisting => return Ok(existing),
+                    DuplicateMode::Reject => return Err(Error::DuplicateItem(existing.id)),
+                    DuplicateMode::Link => {
+                        item.path = existing.path;
+                        return self.item.create(item);
+                    }
+                }
+            }
+
+            let mut item = self.item.create(item)?;
+            let file = Self::build_repo_path(repo, &item)?;
+            let path = file.absolute_path();
+            self.item.change_path(item.id, &path)?;
+            let resource = self.resource.get_or_init(&repo.name)?;
+            moved = Some(resource.move_temp_file(&upload.file, file)?);
+            item.path = path;
+            Ok(item)
+        });
+        // the commit failed after the move, the file goes back to where the upload left it
+        if let (Err(_), Some(file)) = (&result, &moved) {
+            if let Err(e) = file.move_to_file(&upload.file) {
+                error!("putting back {} failed: {}", upload.file.absolute_path(), e);
+            }
+        }
+        result
+    }
+
+    pub fn create_upload_session(&self, repo_id: i64, name: String, caption: Option<String>, size: usize) -> Res<UploadSession> {
+        check_permission(repo_id, UserRole::Manager)?;
+        let repo = self.repo.select_repo_by_id(repo_id)?;
+        let limit = repo.config.common_config()?.max_upload_size;
+        if size > limit {
+            return Err(Error::UploadTooLarge(limit));
+        }
+
         let resource = self.resource.get_or_init(&repo.name)?;
-        resource.write_file(data, file)?;
-        item.path = path;
+        let id = Uuid::new_v4().to_string();
+        resource.temp_file(&id, &content_type::UNKNOWN).touch()?;
+        let meta = UploadSessionMeta { repo_id, user_id: get_user_id()?, name, caption, size, updated_at: Utc::now() };
+        Self::save_upload_session(&resource, &id, &meta)?;
+        Ok(UploadSession::new(id, &meta, 0))
+    }
+
+    pub fn select_upload_session(&self, repo_id: i64, id: &str) -> Res<UploadSession> {
+        let (resource, meta) = self.load_upload_session(repo_id, id)?;
+        let offset = resource.temp_file(id, &content_type::UNKNOWN).size()? as usize;
+        Ok(UploadSession::new(String::from(id), &meta, offset))
+    }
+
+    // the chunk has to continue exactly where the staged bytes end
+    pub fn write_upload_session(&self, repo_id: i64, id: &str, offset: usize) -> Res<UploadSessionWriter> {
+        let (resource, mut meta) = self.load_upload_session(repo_id, id)?;
+        let lock = UploadSessionLock::acquire(&self.busy_sessions, id)?;
+        let file = resource.temp_file(id, &content_type::UNKNOWN);
+        let current = file.open_append()? as usize;
+        if current != offset {
+            return Err(Error::UploadOffsetMismatch(current));
+        }
+
+        meta.updated_at = Utc::now();
+        Self::save_upload_session(&resource, id, &meta)?;
+        Ok(UploadSessionWriter { id: String::from(id), meta, file, offset, _lock: lock })
+    }
+
+    pub fn finish_upload_session(&self, repo_id: i64, id: &str) -> Res<Item> {
+        let (resource, meta) = self.load_upload_session(repo_id, id)?;
+        let _lock = UploadSessionLock::acquire(&self.busy_sessions, id)?;
+        let file = resource.temp_file(id, &content_type::UNKNOWN);
+        let offset = file.size()? as usize;
+        if offset != meta.size {
+            return Err(Error::UploadOffsetMismatch(offset));
+        }
+
+        let repo = self.repo.select_repo_by_id(repo_id)?;
+        let upload = Upload::staged(repo, file.clone())?;
+        let item = self.create(upload, meta.name, meta.caption)?;
+        // the item exists by now, a leftover file is only logged
+        // a duplicate that was not linked leaves the staged bytes behind
+        if file.is_exist() {
+            if let Err(e) = file.remove() {
+                error!("remove staged bytes of upload session {} failed: {}", id, e);
+            }
+        }
+        if let Err(e) = resource.temp_file(id, &content_type::JSON).remove() {
+            error!("remove upload session {} failed: {}", id, e);
+        }
         Ok(item)
     }
 
     pub fn update_extend(&self, id: i64, extend: ItemExtend) -> Res<Item> {
-        let mut item = self.item.select_by_id(id)?;
+        let mut item = self.item.select_live_by_id(id)?;
         check_permission(item.repo_id, UserRole::Manager)?;
         item.extend = extend;
         let item = self.item.update(item)?;
@@ -66,84 +310,571 @@ impl ItemService {
     }
 
     pub fn update_name(&self, id: i64, name: String) -> Res<Item> {
-        let mut item = self.item.select_by_id(id)?;
+        let mut item = self.item.select_live_by_id(id)?;
         check_permission(item.repo_id, UserRole::Manager)?;
         item.name = name;
         let item = self.item.update(item)?;
         Ok(item)
     }
 
+    pub fn update_caption(&self, id: i64, caption: Option<String>) -> Res<Item> {
+        let mut item = self.item.select_live_by_id(id)?;
+        check_permission(item.repo_id, UserRole::Manager)?;
+        item.caption = caption;
+        let item = self.item.update(item)?;
+        Ok(item)
+    }
+
+    // queues the repo for the background thread to read size and exif of every image again,
+    // a repo already queued keeps its progress, start_id picks up where a run cut short by a restart stopped
+    pub fn backfill_extend(&self, repo_id: i64, start_id: Option<i64>) -> Res<BackfillProgress> {
+        check_permission(repo_id, UserRole::Manager)?;
+        self.repo.select_repo_by_id(repo_id)?;
+        let next_id = *self.backfills.lock()?.entry(repo_id).or_insert(start_id.unwrap_or(0));
+        Ok(BackfillProgress { repo_id, next_id: Some(next_id) })
+    }
+
+    pub fn select_backfill(&self, repo_id: i64) -> Res<BackfillProgress> {
+        check_permission(repo_id, UserRole::Manager)?;
+        Ok(BackfillProgress { repo_id, next_id: self.backfills.lock()?.get(&repo_id).copied() })
+    }
+
+    pub fn backfill_pending(&self) -> Res<bool> {
+        Ok(!self.backfills.lock()?.is_empty())
+    }
+
+    // for the background thread, one batch of every queued repo, returns how many items were updated
+    pub fn backfill_batch(&self) -> Res<usize> {
+        let queued: Vec<(i64, i64)> = self.backfills.lock()?.iter().map(|(repo_id, next_id)| (*repo_id, *next_id)).collect();
+        let mut count = 0;
+        for (repo_id, start_id) in queued {
+            // off the queue while the batch runs, one that panics drops the repo instead of coming back on every run
+            self.backfills.lock()?.remove(&repo_id);
+            let next_id = match self.backfill_repo(repo_id, start_id) {
+                Ok((updated, next_id)) => {
+                    count += updated;
+                    next_id
+                }
+                // a repo that fails is dropped, queuing it again goes on from the id it stopped at
+                Err(e) => {
+                    error!("backfill extend of repo {} from {} failed: {}", repo_id, start_id, e);
+                    None
+                }
+            };
+            if let Some(next_id) = next_id {
+                self.backfills.lock()?.insert(repo_id, next_id);
+            }
+        }
+        Ok(count)
+    }
+
+    // cached thumbnails of rotated images were built sideways and are dropped, the next id is none at the end of the repo
+    fn backfill_repo(&self, repo_id: i64, start_id: i64) -> Res<(usize, Option<i64>)> {
+        let repo = self.repo.select_repo_by_id(repo_id)?;
+        let resource = self.resource.get_or_init(&repo.name)?;
+        let items = self.item.select_from(repo_id, start_id, i64::MAX, BACKFILL_BATCH)?;
+        let Some(next_id) = items.last().map(|item| item.id + 1) else {
+            return Ok((0, None));
+        };
+        let mut count = 0;
+        for mut item in items {
+            let file = resource.build_file(&item.path, item.ext);
+            let image_extend = match &mut item.extend {
+                Empty => continue,
+                Picture(extend) => &mut extend.image_extend,
+                Photo(extend) => {
+                    extend.exif = read_exif(&file);
+                    &mut extend.image_extend
+                }
+            };
+            // a file that is gone or broken keeps what it has
+            let Ok((w, h)) = get_size(&file) else {
+                continue;
+            };
+            if (w, h) != (image_extend.w, image_extend.h) {
+                resource.clear_cache(&item.path)?;
+            }
+            *image_extend = Self::build_image_extend(&file, w, h);
+            self.item.update(item)?;
+            count += 1;
+        }
+        Ok((count, Some(next_id)))
+    }
+
     pub fn select_by_id(&self, id: i64) -> Res<Item> {
-        let item = self.item.select_by_id(id)?;
+        let item = self.item.select_live_by_id(id)?;
         check_permission(item.repo_id, UserRole::Viewer)?;
         Ok(item)
     }
 
-    pub fn select_list(&self, repo_id: i64, limit: i64, from_big: bool,
+    // a page ends at limit items, at the end of the list or after scan items were looked at,
+    // a short page with a cursor only means the filters left little of what was scanned
+    pub fn select_list(&self, repo_id: i64, options: &ListOptions,
                        condition: &Option<Vec<Box<dyn ItemCondition>>>,
-                       filter: &Option<Vec<Box<dyn ItemFilter>>>) -> Res<Vec<Item>> {
+                       filter: &Option<Vec<Box<dyn ItemFilter>>>) -> Res<ItemPage> {
         check_permission(repo_id, UserRole::Viewer)?;
-        let limit = min(limit, 100);
+        let limit = options.limit.clamp(0, MAX_LIST_SIZE);
+        let scan = options.scan.unwrap_or(DEFAULT_LIST_SCAN).clamp(limit, MAX_LIST_SCAN) as usize;
+        let limit = limit as usize;
 
-        let mut tun = condition::ItemTun::new(from_big, repo_id, self.item.clone(), self.tag.clone());
+        let cursor = options.cursor.as_deref().map(ItemCursor::decode).transpose()?;
+        // a shuffle keeps its seed from page to page through the cursor
+        let seed = cursor.as_ref().map(|cursor| cursor.seed).or(options.seed).unwrap_or_else(rand::random);
+
+        let mut tun = condition::ItemTun::new(options.from_big, options.order, seed, repo_id, self.item.clone(), self.tag.clone());
         tun.init(condition)?;
-        loop {
-            let mut vec = tun.pull(limit as usize)?;
+        let total = if options.count { Some(tun.count()?) } else { None };
+        if let Some(cursor) = &cursor {
+            tun.resume(cursor)?;
+        }
+
+        let mut items = Vec::new();
+        let mut scanned = 0;
+        // never pull more than the page still takes, what the tun handed out is behind the cursor
+        while items.len() < limit && scanned < scan && !tun.exhausted() {
+            let size = min(limit - items.len(), scan - scanned);
+            let mut vec = tun.pull(size)?;
+            // what the tun handed out, a short pull does not use up the budget
+            scanned += vec.len();
             if vec.is_empty() {
-                return Ok(vec);
+                continue;
             }
 
             if let Some(cs) = &filter {
                 let context = self.build_condition_context(&vec)?;
                 cs.iter().for_each(|c| vec.retain(|item| c.check(item, &context)));
             }
+            items.append(&mut vec);
+        }
+
+        Ok(ItemPage {
+            items,
+            cursor: tun.cursor().map(|cursor| cursor.encode()).transpose()?,
+            total,
+            remaining: if options.count { Some(tun.count()?) } else { None },
+        })
+    }
+
+    // items of the same repo whose perceptual hash differs in at most distance bits, closest first
+    pub fn select_similar(&self, id: i64, distance: u32) -> Res<Vec<SimilarItem>> {
+        let item = self.select_by_id(id)?;
+        let distance = distance.min(MAX_PHASH_DISTANCE);
+        let hashes = self.item.select_phash(item.repo_id)?;
+        let hash = hashes.iter().find(|(item_id, _)| *item_id == id).map(|(_, hash)| *hash).ok_or(Error::NoPerceptualHash)?;
+
+        let mut near: Vec<(u32, i64)> = hashes.iter()
+            .filter(|(item_id, _)| *item_id != id)
+            .map(|(item_id, other)| ((hash ^ other).count_ones(), *item_id))
+            .filter(|(d, _)| *d <= distance)
+            .collect();
+        near.sort_unstable();
+        let ids = near.iter().map(|(_, item_id)| *item_id).collect();
+        let mut items: HashMap<i64, Item> = self.item.select_by_ids(&ids)?.into_iter().map(|item| (item.id, item)).collect();
+        Ok(near.into_iter()
+            .filter_map(|(distance, item_id)| items.remove(&item_id).map(|item| SimilarItem { item, distance }))
+            .collect())
+    }
+
+    // every set of items connected by hashes at most distance bits apart, biggest set first
+    pub fn select_duplicate_groups(&self, repo_id: i64, distance: u32) -> Res<Vec<Vec<Item>>> {
+        check_permission(repo_id, UserRole::Viewer)?;
+        let distance = distance.min(MAX_PHASH_DISTANCE);
+        let hashes = self.item.select_phash(repo_id)?;
+
+        // union find over the pairs, the tree hands out the near hashes without comparing every pair
+        let mut tree = BkTree::new();
+        hashes.iter().enumerate().for_each(|(i, (_, hash))| tree.insert(*hash, i));
+        let mut parent: Vec<usize> = (0..hashes.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for (i, (_, hash)) in hashes.iter().enumerate() {
+            for j in tree.find(*hash, distance) {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[max(a, b)] = min(a, b);
+            }
+        }
+
+        let mut groups: HashMap<usize, Vec<i64>> = HashMap::new();
+        for (i, (item_id, _)) in hashes.iter().enumerate() {
+            groups.entry(root(&mut parent, i)).or_default().push(*item_id);
+        }
+        let mut groups: Vec<Vec<i64>> = groups.into_values().filter(|group| group.len() > 1).collect();
+        groups.sort_unstable_by(|lhs, rhs| rhs.len().cmp(&lhs.len()).then(lhs[0].cmp(&rhs[0])));
+        groups.into_iter().map(|group| self.item.select_by_ids(&group)).collect()
+    }
+
+    // splits the box into grid x grid cells and merges the photos of each cell into one point
+    pub fn geo_cluster(&self, repo_id: i64, min_lat: f64, max_lat: f64, min_lng: f64, max_lng: f64, grid: u32) -> Res<Vec<GeoCluster>> {
+        check_permission(repo_id, UserRole::Viewer)?;
+        let grid = grid.clamp(1, MAX_GEO_GRID);
+        let points = self.item.select_geo(repo_id, min_lat, max_lat, min_lng, max_lng)?;
+        // longitudes are taken as offsets from min_lng so a box over the antimeridian stays continuous
+        let lat_span = (max_lat - min_lat).max(f64::EPSILON);
+        let lng_span = match max_lng - min_lng {
+            span if span > 0.0 => span,
+            span => span + 360.0,
+        };
+        let cell = |offset: f64, span: f64| ((offset / span * grid as f64) as u32).min(grid - 1);
 
-            if !vec.is_empty() {
-                return Ok(vec);
+        let mut cells: HashMap<(u32, u32), (f64, f64, usize, i64)> = HashMap::new();
+        for point in points {
+            let lng_offset = (point.longitude - min_lng).rem_euclid(360.0);
+            let key = (cell(point.latitude - min_lat, lat_span), cell(lng_offset, lng_span));
+            let entry = cells.entry(key).or_insert((0.0, 0.0, 0, point.item_id));
+            entry.0 += point.latitude;
+            entry.1 += lng_offset;
+            entry.2 += 1;
+            entry.3 = max(entry.3, point.item_id);
+        }
+
+        let mut clusters: Vec<GeoCluster> = cells.into_values()
+            .map(|(lat, lng_offset, count, item_id)| GeoCluster {
+                latitude: lat / count as f64,
+                longitude: normalize(min_lng + lng_offset / count as f64),
+                count,
+                item_id,
+            })
+            .collect();
+        clusters.sort_unstable_by(|lhs, rhs| rhs.count.cmp(&lhs.count).then(rhs.item_id.cmp(&lhs.item_id)));
+        Ok(clusters)
+    }
+
+    // moves the item to the trash, tags and file stay until the purge
+    pub fn delete(&self, id: i64) -> Res<()> {
+        let item = self.item.select_by_id(id)?;
+        check_permission(item.repo_id, UserRole::Manager)?;
+        if item.is_deleted {
+            return Err(Error::ItemNotFound);
+        }
+        self.item.delete(id)
+    }
+
+    pub fn restore(&self, id: i64) -> Res<Item> {
+        let item = self.item.select_by_id(id)?;
+        check_permission(item.repo_id, UserRole::Manager)?;
+        if !item.is_deleted {
+            return Err(Error::ItemNotInTrash);
+        }
+        self.item.restore(id)?;
+        self.item.select_by_id(id)
+    }
+
+    // end_id pages backwards through the trash, the first page starts from the newest item
+    pub fn select_trash(&self, repo_id: i64, end_id: Option<i64>, limit: i64) -> Res<Vec<Item>> {
+        check_permission(repo_id, UserRole::Manager)?;
+        self.item.select_deleted(repo_id, end_id.unwrap_or(i64::MAX), limit.clamp(0, MAX_LIST_SIZE))
+    }
+
+    // removes what has been in the trash longer than the repo keeps it
+    pub fn purge_trash(&self, repo_id: i64) -> Res<usize> {
+        check_permission(repo_id, UserRole::Manager)?;
+        let repo = self.repo.select_repo_by_id(repo_id)?;
+        self.purge_repo(&repo)
+    }
+
+    // for the scheduler, there is no user behind it so no permission is checked,
+    // a repo that fails is logged and left for the next run so the others still get purged
+    pub fn purge_expired(&self) -> Res<usize> {
+        let mut count = 0;
+        for repo in self.repo.list_repo()? {
+            match self.purge_repo(&repo) {
+                Ok(purged) => count += purged,
+                Err(e) => error!("purge trash of repo {} failed: {}", repo.id, e),
+            }
+        }
+        Ok(count)
+    }
+
+    // for the scheduler like purge_expired, a session a request is still working on is kept
+    pub fn sweep_upload_sessions(&self) -> Res<usize> {
+        let mut count = 0;
+        for repo in self.repo.list_repo()? {
+            match self.sweep_repo_upload_sessions(&repo) {
+                Ok(swept) => count += swept,
+                Err(e) => error!("sweep upload sessions of repo {} failed: {}", repo.id, e),
+            }
+        }
+        Ok(count)
+    }
+
+    fn sweep_repo_upload_sessions(&self, repo: &Repo) -> Res<usize> {
+        let resource = self.resource.get_or_init(&repo.name)?;
+        let mut count = 0;
+        for id in resource.idle_upload_sessions(UPLOAD_SESSION_TTL)? {
+            let Ok(_lock) = UploadSessionLock::acquire(&self.busy_sessions, &id) else {
+                continue;
+            };
+            let staged = resource.temp_file(&id, &content_type::UNKNOWN);
+            if staged.is_exist() {
+                staged.remove()?;
+            }
+            resource.temp_file(&id, &content_type::JSON).remove()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn purge_repo(&self, repo: &Repo) -> Res<usize> {
+        let retention = chrono::Duration::days(repo.config.common_config()?.trash_retention_days as i64);
+        let deleted_before = Utc::now() - retention;
+        let resource = self.resource.get_or_init(&repo.name)?;
+        let mut count = 0;
+        loop {
+            let items = self.item.select_expired(repo.id, deleted_before, 100)?;
+            if items.is_empty() {
+                return Ok(count);
+            }
+            for item in items {
+                self.purge(&resource, &item)?;
+                count += 1;
             }
         }
     }
 
-    pub fn change_repo(&self, id: i64, repo_id: i64) -> Res<()> {
-        let mut item = self.item.select_by_id(id)?;
+    // the file and its cache only go once no other item links to the same path and the rows are committed,
+    // removing them is cleanup, a failure is logged and the purge goes on
+    fn purge(&self, resource: &RepoResourceManager, item: &Item) -> Res<()> {
+        let orphaned = self.unit.transaction(|| {
+            self.tag.purge_all(item.id)?;
+            self.item.purge(item.id)?;
+            Ok(self.item.count_by_path(item.repo_id, &item.path)? == 0)
+        })?;
+        if orphaned {
+            let removed = resource.clear_cache(&item.path).and_then(|_| {
+                let file = resource.build_file(&item.path, item.ext);
+                if file.is_exist() { file.remove() } else { Ok(()) }
+            });
+            if let Err(e) = removed {
+                error!("remove file {} of purged item {} failed: {}", item.path, item.id, e);
+            }
+        }
+        Ok(())
+    }
+
+    // the file is put back when the commit fails after it was moved
+    pub fn change_repo(&self, id: i64, repo_id: i64) -> Res<Item> {
+        let mut moves = Vec::new();
+        let result = self.unit.transaction(|| {
+            let (item, file_move) = self.move_to_repo(id, repo_id)?;
+            moves.extend(file_move);
+            Ok(item)
+        });
+        if result.is_err() {
+            FileMove::revert_all(&moves);
+        }
+        result
+    }
+
+    pub fn batch_delete(&self, ids: &[i64]) -> Res<BatchResult> {
+        batch(&self.unit, ids, |id| self.delete(id))
+    }
+
+    pub fn batch_restore(&self, ids: &[i64]) -> Res<BatchResult> {
+        batch(&self.unit, ids, |id| self.restore(id).map(|_| ()))
+    }
+
+    // files are moved item by item, when the batch is rolled back they are put back as well
+    pub fn batch_change_repo(&self, ids: &[i64], repo_id: i64) -> Res<BatchResult> {
+        let mut moves = Vec::new();
+        let result = batch(&self.unit, ids, |id| {
+            let (_, file_move) = self.move_to_repo(id, repo_id)?;
+            moves.extend(file_move);
+            Ok(())
+        });
+        if !matches!(result, Ok(BatchResult { applied: true, .. })) {
+            FileMove::revert_all(&moves);
+        }
+        result
+    }
+
+    pub fn batch_patch_extend(&self, ids: &[i64], patch: &serde_json::Value) -> Res<BatchResult> {
+        batch(&self.unit, ids, |id| self.patch_extend(id, patch).map(|_| ()))
+    }
+
+    // the patch is merged into the json of the extend, the result still has to be an extend of the same kind
+    pub fn patch_extend(&self, id: i64, patch: &serde_json::Value) -> Res<Item> {
+        let mut item = self.item.select_live_by_id(id)?;
+        check_permission(item.repo_id, UserRole::Manager)?;
+        let extend = json::patch(&item.extend, patch)?;
+        if std::mem::discriminant(&extend) != std::mem::discriminant(&item.extend) {
+            return Err(Error::ParseJsonError(String::from("the patch changes the kind of the extend")));
+        }
+        item.extend = extend;
+        self.item.update(item)
+    }
+
+    // the file and its cache move into the layout of the target repo and the extend is built again for its type,
+    // a file other items of the old repo link to is copied instead
+    fn move_to_repo(&self, id: i64, repo_id: i64) -> Res<(Item, Option<FileMove>)> {
+        let mut item = self.item.select_live_by_id(id)?;
         check_permission(item.repo_id, UserRole::Manager)?;
         check_permission(repo_id, UserRole::Manager)?;
+        if item.repo_id == repo_id {
+            return Ok((item, None));
+        }
+
+        let source = self.repo.select_repo_by_id(item.repo_id)?;
+        let target = self.repo.select_repo_by_id(repo_id)?;
+        let source_resource = self.resource.get_or_init(&source.name)?;
+        let old_path = item.path.clone();
+        let shared = self.item.count_by_path(item.repo_id, &old_path)? > 1;
+
         item.repo_id = repo_id;
+        item.path = Self::build_repo_path(&target, &item)?.absolute_path();
+        let extend = Self::build_extend(&target, &source_resource.build_file(&old_path, item.ext), item.ext)?;
+        // author and url are curated by hand, only what is read from the file is built again
+        item.extend = match (extend, &item.extend) {
+            (Picture(extend), Picture(old)) => Picture(PictureExtend { author: old.author, url: old.url.clone(), ..extend }),
+            (extend, _) => extend,
+        };
+
         self.item.change_repo(id, repo_id)?;
-        Ok(())
+        self.item.change_path(id, &item.path)?;
+        self.tag.move_item(id, repo_id)?;
+        let item = self.item.update(item)?;
+
+        // the file goes last, as in create, a failed move rolls back the rows
+        let file_move = FileMove {
+            source: source_resource,
+            target: self.resource.get_or_init(&target.name)?,
+            old_path,
+            new_path: item.path.clone(),
+            ext: item.ext,
+            shared,
+        };
+        file_move.apply()?;
+        Ok((item, Some(file_move)))
     }
 
-    pub fn read_item(&self, id: i64) -> Res<FileStream> {
-        let item = self.item.select_by_id(id)?;
+    pub fn read_item(&self, id: i64) -> Res<ItemContent> {
+        let item = self.item.select_live_by_id(id)?;
         check_permission(item.repo_id, UserRole::Viewer)?;
         let repo = self.repo.select_repo_by_id(item.repo_id)?;
         let resource = self.resource.get_or_init(&repo.name)?;
-        resource.build_file(&item.path, item.ext).as_stream()
+        let file = resource.build_file(&item.path, item.ext);
+        ItemContent::new(&item, file, item.ext, item.name.clone(), "")
     }
 
-    pub fn read_thumbnail(&self, id: i64) -> Res<FileStream> {
-        let item = self.item.select_by_id(id)?;
+    // an animation gets its first frame as the poster, or a short animated gif when asked for
+    pub fn read_thumbnail(&self, id: i64, animated: bool) -> Res<ItemContent> {
+        let item = self.item.select_live_by_id(id)?;
         check_permission(item.repo_id, UserRole::Viewer)?;
         let repo = self.repo.select_repo_by_id(item.repo_id)?;
         let resource = self.resource.get_or_init(&repo.name)?;
-        if item.size <= self.max_thumbnail_size {
-            resource.build_file(&item.path, item.ext).as_stream()
+        let name = format!("{}-thumbnail", item.id);
+        match item.ext.file {
+            FileType::Image => {}
+            // vectors scale by themselves, the source is the thumbnail
+            FileType::Vector => {
+                let file = resource.build_file(&item.path, item.ext);
+                return ItemContent::new(&item, file, item.ext, name, "");
+            }
+            FileType::Unknown => return Err(Error::UnknownFileContentType),
+            FileType::Plain |
+            FileType::Video |
+            FileType::Document |
+            FileType::Archive => return Err(Error::ThumbnailNotSupported),
+        }
+        let is_animation = match &item.extend {
+            ItemExtend::Picture(extend) => extend.animated,
+            ItemExtend::Photo(extend) => extend.animated,
+            ItemExtend::Empty => false,
+        };
+        // the etag follows what is served, the original shares the one of the item
+        if item.size <= self.max_thumbnail_size && animated == is_animation {
+            let file = resource.build_file(&item.path, item.ext);
+            ItemContent::new(&item, file, item.ext, name, "")
+        } else if animated && is_animation {
+            let mut thumbnail_file_node = resource.build_rendition_file(&item.path, "thumbnail-animated", &content_type::GIF89A);
+            if !thumbnail_file_node.is_exist() {
+                let mut origin_file_node = resource.build_file(&item.path, item.ext);
+                build_animated_thumbnail_from_file(&mut origin_file_node, &mut thumbnail_file_node)?;
+            }
+            ItemContent::new(&item, thumbnail_file_node, &content_type::GIF89A, format!("{}-thumbnail-animated", item.id), "-thumbnail-animated")
         } else {
             let mut thumbnail_file_node = resource.build_thumbnail_file(&item.path);
-            if thumbnail_file_node.is_exist() {
-                thumbnail_file_node.as_stream()
-            } else {
-                let mut origin_file_node = resource.build_file(&item.path, item.ext);
-                match item.ext.file {
-                    FileType::Unknown => Err(Error::UnknownFileContentType),
-                    FileType::Plain => Err(Error::UnknownFileContentType),
-                    FileType::Image => {
-                        build_thumbnail_from_file(&mut origin_file_node, &mut thumbnail_file_node)?;
-                        thumbnail_file_node.as_stream()
-                    },
+            if !thumbnail_file_node.is_exist() {
+                if !can_decode(item.ext) {
+                    return Err(Error::ThumbnailNotSupported);
                 }
+                let mut origin_file_node = resource.build_file(&item.path, item.ext);
+                build_thumbnail_from_file(&mut origin_file_node, &mut thumbnail_file_node)?;
             }
+            ItemContent::new(&item, thumbnail_file_node, &content_type::PNG, name, "-thumbnail")
+        }
+    }
+
+    pub fn read_rendition(&self, id: i64, width: u32, height: u32, fit: Fit, format: RenditionFormat) -> Res<ItemContent> {
+        let item = self.item.select_live_by_id(id)?;
+        check_permission(item.repo_id, UserRole::Viewer)?;
+        let repo = self.repo.select_repo_by_id(item.repo_id)?;
+        let sizes = &repo.config.common_config()?.rendition_sizes;
+        if let Some(size) = [width, height].into_iter().find(|size| !sizes.contains(size)) {
+            return Err(Error::RenditionSizeNotAllowed(size));
+        }
+        if !matches!(item.ext.file, FileType::Image) || !can_decode(item.ext) {
+            return Err(Error::ThumbnailNotSupported);
+        }
+
+        let resource = self.resource.get_or_init(&repo.name)?;
+        let key = format!("{}x{}-{:?}", width, height, fit);
+        let mut rendition_file_node = resource.build_rendition_file(&item.path, &key, format.content_type());
+        if !rendition_file_node.is_exist() {
+            let mut origin_file_node = resource.build_file(&item.path, item.ext);
+            build_rendition_from_file(&mut origin_file_node, &mut rendition_file_node, width, height, fit, format)?;
+        }
+        // the same size in another format is other bytes, it needs an etag of its own
+        let variant = format!("-{}-{:?}", key, format);
+        ItemContent::new(&item, rendition_file_node, format.content_type(), format!("{}-{}", item.id, key), &variant)
+    }
+
+    // a frame of an animation or a page of a tiff, counted from 0
+    pub fn read_frame(&self, id: i64, index: u32, format: RenditionFormat) -> Res<ItemContent> {
+        let item = self.item.select_live_by_id(id)?;
+        check_permission(item.repo_id, UserRole::Viewer)?;
+        if !matches!(item.ext.file, FileType::Image) || !can_decode(item.ext) {
+            return Err(Error::ThumbnailNotSupported);
+        }
+
+        let repo = self.repo.select_repo_by_id(item.repo_id)?;
+        let resource = self.resource.get_or_init(&repo.name)?;
+        let key = format!("frame-{}", index);
+        let mut frame_file_node = resource.build_rendition_file(&item.path, &key, format.content_type());
+        if !frame_file_node.is_exist() {
+            let mut origin_file_node = resource.build_file(&item.path, item.ext);
+            build_frame_from_file(&mut origin_file_node, &mut frame_file_node, index, format)?;
         }
+        let variant = format!("-{}-{:?}", key, format);
+        ItemContent::new(&item, frame_file_node, format.content_type(), format!("{}-{}", item.id, key), &variant)
+    }
+
+    fn load_upload_session(&self, repo_id: i64, id: &str) -> Res<(Arc<RepoResourceManager>, UploadSessionMeta)> {
+        check_permission(repo_id, UserRole::Manager)?;
+        // the id becomes a file name, anything but a uuid could walk out of .temp
+        Uuid::parse_str(id).map_err(|_| Error::NoSuchUploadSession)?;
+        let repo = self.repo.select_repo_by_id(repo_id)?;
+        let resource = self.resource.get_or_init(&repo.name)?;
+        let mut meta_file = resource.temp_file(id, &content_type::JSON);
+        if !meta_file.is_exist() {
+            return Err(Error::NoSuchUploadSession);
+        }
+
+        let meta: UploadSessionMeta = meta_file.read_json()?;
+        let idle = Utc::now().signed_duration_since(meta.updated_at).to_std().unwrap_or_default();
+        if meta.repo_id != repo_id || meta.user_id != get_user_id()? || idle > UPLOAD_SESSION_TTL {
+            return Err(Error::NoSuchUploadSession);
+        }
+        Ok((resource, meta))
+    }
+
+    fn save_upload_session(resource: &RepoResourceManager, id: &str, meta: &UploadSessionMeta) -> Res<()> {
+        resource.temp_file(id, &content_type::JSON).write(json::stringify(meta)?.as_bytes())?;
+        Ok(())
     }
 
     fn build_repo_path(repo: &Repo, item: &Item) -> Res<FileNode> {
@@ -167,37 +898,210 @@ impl ItemService {
                 }
             }
         };
-        match &repo.config {
-            RepoConfig::Illustration(config) => callback(config),
-            RepoConfig::UnSupportConfig => unreachable!(),
-        }
+        callback(repo.config.common_config()?)
     }
 
-    fn build_extend(repo: &Repo, data: &Vec<u8>, file_type: &ContentType) -> Res<ItemExtend> {
+    fn build_extend(repo: &Repo, file: &FileNode, file_type: &ContentType) -> Res<ItemExtend> {
         match file_type.file {
             FileType::Unknown => Err(Error::UnknownFileContentType),
-            FileType::Plain => Ok(Empty),
+            FileType::Plain |
+            FileType::Vector |
+            FileType::Video |
+            FileType::Document |
+            FileType::Archive => Ok(Empty),
+            FileType::Image if !can_decode(file_type) => Ok(Empty),
             FileType::Image => {
-                let (w, h) = get_size(data)?;
+                let (w, h) = get_size(file)?;
+                let image_extend = Self::build_image_extend(file, w, h);
                 match repo.config {
                     RepoConfig::Illustration(_) => {
-                        Ok(Picture(PictureExtend { image_extend: ImageExtend { w, h }, author: None, url: None }))
+                        Ok(Picture(PictureExtend { image_extend, author: None, url: None }))
                     }
                     _ => {
-                        Ok(Photo(PhotoExtend { image_extend: ImageExtend { w, h } }))
+                        Ok(Photo(PhotoExtend { image_extend, exif: read_exif(file) }))
                     }
                 }
             }
         }
     }
 
+    // the header was fine, an image whose pixels do not decode is still stored, just without palette and hash
+    fn build_image_extend(file: &FileNode, w: u32, h: u32) -> ImageExtend {
+        let (frames, image) = match load_file(file) {
+            Ok((frames, image)) => (frames, Some(image)),
+            Err(_) => (FrameInfo::default(), None),
+        };
+        let mut extend = ImageExtend {
+            w, h,
+            palette: Vec::new(), phash: None, blurhash: None,
+            frames: frames.count, duration: frames.duration, animated: frames.animated,
+        };
+        if let Some(image) = image {
+            extend.palette = dominant_colors(&image, PALETTE_SIZE).into_iter()
+                .map(|[r, g, b, a]| RgbColor { r, g, b, a })
+                .collect();
+            extend.phash = Some(format!("{:016x}", perceptual_hash(&image)));
+            extend.blurhash = Some(placeholder(&image));
+        }
+        extend
+    }
+
     fn build_condition_context(&self, _: &Vec<Item>) -> Result<ConditionContext, Error> {
         Ok(ConditionContext {})
     }
 }
 
+// where move_to_repo put a file, to undo it when the rows it belongs to are rolled back
+struct FileMove {
+    source: Arc<RepoResourceManager>,
+    target: Arc<RepoResourceManager>,
+    old_path: String,
+    new_path: String,
+    ext: &'static ContentType,
+    // other items still use the old file, it was copied
+    shared: bool,
+}
+
+impl FileMove {
+    fn apply(&self) -> Res<()> {
+        let old_file = self.source.build_file(&self.old_path, self.ext);
+        let new_file = self.target.build_file(&self.new_path, self.ext);
+        if self.shared {
+            old_file.copy_to_file(&new_file)?;
+        } else {
+            old_file.move_to_file(&new_file)?;
+        }
+        // the row is rolled back on error, so the file has to be where it points again
+        if let Err(e) = self.source.move_cache(&self.old_path, &self.target, &self.new_path, self.shared) {
+            if let Err(revert) = self.revert() {
+                error!("putting back {} failed: {}", self.old_path, revert);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // latest first, one that fails is logged and the others are still put back
+    fn revert_all(moves: &[FileMove]) {
+        for file_move in moves.iter().rev() {
+            if let Err(e) = file_move.revert() {
+                error!("putting back {} failed: {}", file_move.old_path, e);
+            }
+        }
+    }
+
+    fn revert(&self) -> Res<()> {
+        let new_file = self.target.build_file(&self.new_path, self.ext);
+        if self.shared {
+            self.target.clear_cache(&self.new_path)?;
+            new_file.remove()
+        } else {
+            new_file.move_to_file(&self.source.build_file(&self.old_path, self.ext))?;
+            self.target.move_cache(&self.new_path, &self.source, &self.old_path, false)
+        }
+    }
+}
+
+impl Upload {
+    // picks up bytes a session has already staged in .temp
+    fn staged(repo: Repo, mut file: FileNode) -> Res<Self> {
+        let limit = repo.config.common_config()?.max_upload_size;
+        let mut upload = Upload { repo, file: file.clone(), head: Vec::with_capacity(UPLOAD_HEAD_SIZE), size: 0, limit, hasher: ContentHasher::new(), keep: true };
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let size = file.read(&mut buffer)?;
+            if size == 0 {
+                return Ok(upload);
+            }
+            upload.digest(&buffer[..size])?;
+        }
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) -> Res<()> {
+        self.digest(chunk)?;
+        self.file.append(chunk)
+    }
+
+    fn digest(&mut self, chunk: &[u8]) -> Res<()> {
+        self.size += chunk.len();
+        if self.size > self.limit {
+            return Err(Error::UploadTooLarge(self.limit));
+        }
+        if self.head.len() < UPLOAD_HEAD_SIZE {
+            let take = min(UPLOAD_HEAD_SIZE - self.head.len(), chunk.len());
+            self.head.extend_from_slice(&chunk[..take]);
+        }
+        self.hasher.update(chunk);
+        Ok(())
+    }
+}
+
+impl UploadSession {
+    fn new(id: String, meta: &UploadSessionMeta, offset: usize) -> Self {
+        let expires_at = meta.updated_at + chrono::Duration::from_std(UPLOAD_SESSION_TTL).unwrap_or_default();
+        Self { id, repo_id: meta.repo_id, size: meta.size, offset, expires_at }
+    }
+}
+
+impl ItemContent {
+    // the content hash makes a strong validator, older items without one fall back to id and creation time
+    fn new(item: &Item, file: FileNode, content_type: &'static ContentType, name: String, variant: &str) -> Res<Self> {
+        let size = file.size()?;
+        let etag = match &item.hash {
+            Some(hash) => format!("{}{}", hash, variant),
+            None => format!("{}-{}{}", item.id, item.created_at.timestamp(), variant),
+        };
+        let name = if name.to_lowercase().ends_with(content_type.ext) { name } else { name + content_type.ext };
+        Ok(Self { file, content_type, size, name, etag, modified: item.created_at })
+    }
+}
+
+impl UploadSessionWriter {
+    pub fn write(&mut self, chunk: &[u8]) -> Res<()> {
+        if self.offset + chunk.len() > self.meta.size {
+            return Err(Error::UploadTooLarge(self.meta.size));
+        }
+        self.file.append(chunk)?;
+        self.offset += chunk.len();
+        Ok(())
+    }
+
+    // the state after the written chunks, without looking up the session again
+    pub fn session(&self) -> UploadSession {
+        UploadSession::new(self.id.clone(), &self.meta, self.offset)
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        // already gone once create has moved it into place
+        if !self.keep {
+            let _ = self.file.remove();
+        }
+    }
+}
+
+impl UploadSessionLock {
+    fn acquire(busy: &Arc<Mutex<HashSet<String>>>, id: &str) -> Res<Self> {
+        if !busy.lock()?.insert(String::from(id)) {
+            return Err(Error::UploadSessionBusy);
+        }
+        Ok(Self { id: String::from(id), busy: busy.clone() })
+    }
+}
+
+impl Drop for UploadSessionLock {
+    fn drop(&mut self) {
+        if let Ok(mut busy) = self.busy.lock() {
+            busy.remove(&self.id);
+        }
+    }
+}
+
 pub mod filter {
+    use crate::common::{color, Exif};
     use crate::core::manager::{Item, ItemExtend};
+    use chrono::NaiveDateTime;
     use std::cmp::{max, min};
 
     #[derive(serde::Serialize, serde::Deserialize)]
@@ -245,6 +1149,33 @@ pub mod filter {
         pub compare: CompareType,
     }
 
+    // matched against "make model" of the exif
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct CameraFilter {
+        pub camera: String,
+        pub compare: CompareType,
+    }
+
+    // an item matches when one of its dominant colors is close enough, distance is in delta E
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct ColorFilter {
+        pub r: u8,
+        pub g: u8,
+        pub b: u8,
+        #[serde(default = "default_color_distance")]
+        pub distance: f64,
+    }
+
+    fn default_color_distance() -> f64 {
+        20.0
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct TakenTimeFilter {
+        pub start: Option<NaiveDateTime>,
+        pub end: Option<NaiveDateTime>,
+    }
+
     impl ItemFilter for SizeFilter {
         fn check(&self, item: &Item, _: &ConditionContext) -> bool {
             let checker = |w: u32, h: u32| {
@@ -307,15 +1238,63 @@ pub mod filter {
             }
         }
     }
+
+    impl ItemFilter for CameraFilter {
+        fn check(&self, item: &Item, _: &ConditionContext) -> bool {
+            let camera = match exif(item) {
+                None => return false,
+                Some(exif) => match (&exif.make, &exif.model) {
+                    (None, None) => return false,
+                    (make, model) => [make, model].into_iter().flatten().cloned().collect::<Vec<_>>().join(" "),
+                }
+            };
+            match self.compare {
+                CompareType::Exactly => self.camera == camera,
+                CompareType::Prefix => camera.starts_with(&self.camera),
+                CompareType::Suffix => camera.ends_with(&self.camera),
+                CompareType::Includes => camera.contains(&self.camera),
+                CompareType::Excludes => !camera.contains(&self.camera)
+            }
+        }
+    }
+
+    impl ItemFilter for ColorFilter {
+        fn check(&self, item: &Item, _: &ConditionContext) -> bool {
+            let palette = match &item.extend {
+                ItemExtend::Empty => return false,
+                ItemExtend::Picture(extend) => &extend.palette,
+                ItemExtend::Photo(extend) => &extend.palette,
+            };
+            palette.iter().any(|c| color::distance([c.r, c.g, c.b], [self.r, self.g, self.b]) <= self.distance)
+        }
+    }
+
+    impl ItemFilter for TakenTimeFilter {
+        fn check(&self, item: &Item, _: &ConditionContext) -> bool {
+            match exif(item).and_then(|exif| exif.taken_at) {
+                None => false,
+                Some(taken_at) => self.start.map(|v| taken_at >= v).unwrap_or(true)
+                    && self.end.map(|v| taken_at < v).unwrap_or(true),
+            }
+        }
+    }
+
+    fn exif(item: &Item) -> Option<&Exif> {
+        match &item.extend {
+            ItemExtend::Photo(extend) => extend.exif.as_ref(),
+            _ => None,
+        }
+    }
 }
 
 pub mod condition {
-    use crate::common::Res;
+    use crate::common::{geo, json, natural, Error, Res};
     use crate::core::manager::tag::TagManager;
     use crate::core::manager::{Item, ItemManager};
+    use crate::core::repository::{SortColumn, SortQuery};
     use chrono::{DateTime, Utc};
-    use std::cmp::{max, min};
-    use std::collections::HashSet;
+    use std::cmp::{max, min, Ordering};
+    use std::collections::{HashMap, HashSet};
     use std::sync::Arc;
 
     pub struct ItemTun {
@@ -324,6 +1303,18 @@ pub mod condition {
         from_big: bool,
         repo_id: i64,
         id_list: Option<Vec<i64>>,
+        // id_list is in relevance order and must not be sorted by id
+        ranked: bool,
+        // ids of a ranked id_list handed out so far, the range tracks the others
+        offset: usize,
+        // a pull over the id range came back short
+        drained: bool,
+        order: ItemOrder,
+        seed: u64,
+        // names of the items in id_list for the name order
+        values: HashMap<i64, SortValue>,
+        // the last item handed out in a sorted order
+        last: Option<SortPosition>,
 
         item: Arc<ItemManager>,
         tag: Arc<TagManager>,
@@ -333,6 +1324,50 @@ pub mod condition {
         fn apply(&self, tun: &mut ItemTun) -> Res<()>;
     }
 
+    // from_big turns every order around, items without a value for the order come last either way
+    #[derive(Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+    pub enum ItemOrder {
+        #[default]
+        Id,
+        Name,
+        Size,
+        // width times height
+        Area,
+        // width over height
+        AspectRatio,
+        TakenAt,
+        TagCount,
+        Random,
+    }
+
+    #[derive(Clone, serde::Serialize, serde::Deserialize)]
+    enum SortValue {
+        // compared the natural way
+        Text(String),
+        Number(f64),
+    }
+
+    // the value has to parse back bit for bit or the item it belongs to is handed out again,
+    // serde_json only guarantees that with float_roundtrip
+    #[derive(Clone, serde::Serialize, serde::Deserialize)]
+    struct SortPosition {
+        value: Option<SortValue>,
+        id: i64,
+    }
+
+    // where a list stopped, handed to the client as an opaque token
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct ItemCursor {
+        repo_id: i64,
+        from_big: bool,
+        start_id: i64,
+        end_id: i64,
+        offset: usize,
+        order: ItemOrder,
+        pub(super) seed: u64,
+        last: Option<SortPosition>,
+    }
+
     #[derive(serde::Serialize, serde::Deserialize)]
     pub struct StartIdCondition {
         pub id: i64,
@@ -358,9 +1393,34 @@ pub mod condition {
         pub tags: Vec<i64>,
     }
 
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct SearchCondition {
+        pub keyword: String,
+    }
+
+    // photos taken inside the box, min_lng > max_lng crosses the antimeridian
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct BoundingBoxCondition {
+        pub min_lat: f64,
+        pub max_lat: f64,
+        pub min_lng: f64,
+        pub max_lng: f64,
+    }
+
+    // photos taken within radius meters of the point
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct RadiusCondition {
+        pub latitude: f64,
+        pub longitude: f64,
+        pub radius: f64,
+    }
+
     impl ItemTun {
-        pub fn new(from_big: bool, repo_id: i64, item: Arc<ItemManager>, tag: Arc<TagManager>) -> Self {
-            Self { start_id: 0, end_id: 0x7FFFFFFFFFFFFFFF, from_big, repo_id, id_list: None, item, tag }
+        pub fn new(from_big: bool, order: ItemOrder, seed: u64, repo_id: i64, item: Arc<ItemManager>, tag: Arc<TagManager>) -> Self {
+            Self {
+                start_id: 0, end_id: 0x7FFFFFFFFFFFFFFF, from_big, repo_id, id_list: None, ranked: false, offset: 0, drained: false,
+                order, seed, values: HashMap::new(), last: None, item, tag,
+            }
         }
 
         pub fn init(&mut self, condition_option: &Option<Vec<Box<dyn ItemCondition>>>) -> Res<()> {
@@ -373,25 +1433,139 @@ pub mod condition {
             }
 
             if let Some(id_list) = &mut self.id_list {
-                if self.from_big {
-                    id_list.sort_unstable_by(|lhs, rhs| rhs.cmp(lhs))
-                } else {
-                    id_list.sort_unstable_by(|lhs, rhs| lhs.cmp(rhs))
-                };
+                id_list.retain(|id| *id >= self.start_id && *id < self.end_id);
+                if !self.ranked {
+                    if self.from_big {
+                        id_list.sort_unstable_by(|lhs, rhs| rhs.cmp(lhs))
+                    } else {
+                        id_list.sort_unstable_by(|lhs, rhs| lhs.cmp(rhs))
+                    }
+                }
             }
+            if self.order == ItemOrder::Name {
+                self.sort_by_name()?;
+            }
+            Ok(())
+        }
+
+        // sqlite has no natural order, so the names of what the conditions left are sorted here,
+        // for the ids they left or for their id range when none of them picks ids. it wins over the relevance of a search
+        fn sort_by_name(&mut self) -> Res<()> {
+            let names = match &self.id_list {
+                Some(id_list) => self.item.select_names_by_ids(id_list)?,
+                None => self.item.select_names(self.repo_id, self.start_id, self.end_id)?,
+            };
+            let mut sorted: Vec<(Option<SortValue>, i64)> = names.into_iter()
+                .map(|(id, name)| (Some(SortValue::Text(name)), id))
+                .collect();
+            sorted.sort_by(|lhs, rhs| compare((lhs.0.as_ref(), lhs.1), (rhs.0.as_ref(), rhs.1), self.from_big));
+            self.id_list = Some(sorted.iter().map(|(_, id)| *id).collect());
+            self.values = sorted.into_iter().filter_map(|(value, id)| value.map(|value| (id, value))).collect();
+            self.ranked = true;
+            Ok(())
+        }
+
+        // continues after the page the cursor was made for, the conditions have to be the same as there
+        pub fn resume(&mut self, cursor: &ItemCursor) -> Res<()> {
+            if cursor.repo_id != self.repo_id || cursor.from_big != self.from_big || cursor.order != self.order || cursor.seed != self.seed {
+                return Err(Error::InvalidCursor);
+            }
+            self.start_id = max(self.start_id, cursor.start_id);
+            self.end_id = min(self.end_id, cursor.end_id);
+            if let Some(id_list) = &mut self.id_list {
+                match self.order {
+                    // by value rather than by position, items added or removed since do not shift the pages
+                    ItemOrder::Name => if let Some(last) = &cursor.last {
+                        id_list.retain(|id| {
+                            compare((self.values.get(id), *id), (last.value.as_ref(), last.id), self.from_big) == Ordering::Greater
+                        });
+                    },
+                    ItemOrder::Id if self.ranked => {
+                        id_list.drain(..min(cursor.offset, id_list.len()));
+                    }
+                    ItemOrder::Id => id_list.retain(|id| *id >= self.start_id && *id < self.end_id),
+                    // sqlite picks up after the last position by itself
+                    _ => {}
+                }
+            }
+            self.offset = cursor.offset;
+            self.last = cursor.last.clone();
             Ok(())
         }
 
+        pub fn exhausted(&self) -> bool {
+            match &self.id_list {
+                Some(id_list) if id_list.is_empty() => true,
+                // in an order sqlite pages, the id_list only narrows the items down
+                Some(_) if self.order.column(self.seed).is_none() => false,
+                _ => self.drained || self.start_id >= self.end_id,
+            }
+        }
+
+        pub fn cursor(&self) -> Option<ItemCursor> {
+            if self.exhausted() {
+                return None;
+            }
+            Some(ItemCursor {
+                repo_id: self.repo_id,
+                from_big: self.from_big,
+                start_id: self.start_id,
+                end_id: self.end_id,
+                offset: self.offset,
+                order: self.order,
+                seed: self.seed,
+                last: self.last.clone(),
+            })
+        }
+
+        // items not pulled yet
+        pub fn count(&self) -> Res<i64> {
+            if let Some(column) = self.order.column(self.seed) {
+                return if self.drained { Ok(0) } else { self.item.count_sorted(&self.sort_query(column)) };
+            }
+            match &self.id_list {
+                Some(id_list) => Ok(id_list.len() as i64),
+                None if self.drained => Ok(0),
+                None => self.item.count_range(self.repo_id, self.start_id, self.end_id),
+            }
+        }
+
+        // keeps the order of the current id_list, if there is one
+        fn intersect(&mut self, item_id_list: Vec<i64>) {
+            self.id_list = Some(match &self.id_list {
+                None => item_id_list,
+                Some(id_list) => {
+                    let item_id_set = item_id_list.iter().collect::<HashSet<_>>();
+                    id_list.iter().filter(|id| item_id_set.contains(id)).copied().collect()
+                }
+            });
+        }
+
         pub fn pull(&mut self, limit: usize) -> Res<Vec<Item>> {
+            if let Some(column) = self.order.column(self.seed) {
+                return self.pull_sorted(column, limit);
+            }
             let mut items = if let Some(id_list) = &mut self.id_list {
-                let vec: Vec<_> = id_list.drain(..limit).collect();
-                self.item.select_by_ids(&vec)?
+                let vec: Vec<_> = id_list.drain(..min(limit, id_list.len())).collect();
+                self.offset += vec.len();
+                if self.order == ItemOrder::Name {
+                    self.last = vec.last().map(|id| SortPosition { value: self.values.get(id).cloned(), id: *id });
+                }
+                let mut items = self.item.select_by_ids(&vec)?;
+                if self.ranked {
+                    items.sort_by_key(|item| vec.iter().position(|id| *id == item.id));
+                    return Ok(items);
+                }
+                items
             } else if self.from_big {
                 self.item.select_to(self.repo_id, self.start_id, self.end_id, limit as i64)?
             } else {
                 self.item.select_from(self.repo_id, self.start_id, self.end_id, limit as i64)?
             };
-            
+            if self.id_list.is_none() && items.len() < limit {
+                self.drained = true;
+            }
+
             if items.is_empty() {
                 return Ok(items);
             }
@@ -406,6 +1580,97 @@ pub mod condition {
 
             Ok(items)
         }
+
+        // sqlite hands out the page in the order, what the conditions picked only narrows it down
+        fn pull_sorted(&mut self, column: SortColumn, limit: usize) -> Res<Vec<Item>> {
+            let sorted = self.item.select_sorted(&self.sort_query(column), limit as i64)?;
+            if sorted.len() < limit {
+                self.drained = true;
+            }
+            self.offset += sorted.len();
+            if let Some((id, value)) = sorted.last() {
+                self.last = Some(SortPosition { value: value.map(SortValue::Number), id: *id });
+            }
+            let ids: Vec<i64> = sorted.into_iter().map(|(id, _)| id).collect();
+            let mut items = self.item.select_by_ids(&ids)?;
+            items.sort_by_key(|item| ids.iter().position(|id| *id == item.id));
+            Ok(items)
+        }
+
+        fn sort_query(&self, column: SortColumn) -> SortQuery<'_> {
+            SortQuery {
+                repo_id: self.repo_id,
+                start_id: self.start_id,
+                end_id: self.end_id,
+                ids: self.id_list.as_deref(),
+                column,
+                descending: self.from_big,
+                after: self.last.as_ref().map(|last| (last.value.as_ref().and_then(SortValue::number), last.id)),
+            }
+        }
+    }
+
+    impl ItemOrder {
+        // what sqlite orders by, none for the id and for the natural order of names
+        fn column(&self, seed: u64) -> Option<SortColumn> {
+            match self {
+                ItemOrder::Id | ItemOrder::Name => None,
+                ItemOrder::Size => Some(SortColumn::Size),
+                ItemOrder::Area => Some(SortColumn::Area),
+                ItemOrder::AspectRatio => Some(SortColumn::AspectRatio),
+                ItemOrder::TakenAt => Some(SortColumn::TakenAt),
+                ItemOrder::TagCount => Some(SortColumn::TagCount),
+                ItemOrder::Random => Some(SortColumn::Random(seed)),
+            }
+        }
+    }
+
+    impl SortValue {
+        fn number(&self) -> Option<f64> {
+            match self {
+                SortValue::Number(number) => Some(*number),
+                SortValue::Text(_) => None,
+            }
+        }
+
+        fn compare(&self, other: &Self) -> Ordering {
+            match (self, other) {
+                (SortValue::Text(lhs), SortValue::Text(rhs)) => natural::compare(lhs, rhs),
+                (SortValue::Number(lhs), SortValue::Number(rhs)) => lhs.total_cmp(rhs),
+                (SortValue::Number(_), SortValue::Text(_)) => Ordering::Less,
+                (SortValue::Text(_), SortValue::Number(_)) => Ordering::Greater,
+            }
+        }
+    }
+
+    // the id breaks ties so every item has a place of its own
+    fn compare(lhs: (Option<&SortValue>, i64), rhs: (Option<&SortValue>, i64), descending: bool) -> Ordering {
+        let order = match (lhs.0, rhs.0) {
+            (Some(l), Some(r)) => l.compare(r).then(lhs.1.cmp(&rhs.1)),
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => lhs.1.cmp(&rhs.1),
+        };
+        if descending { order.reverse() } else { order }
+    }
+
+    impl ItemCursor {
+        // hex of the json, clients only hand it back
+        pub fn encode(&self) -> Res<String> {
+            Ok(json::stringify(self)?.bytes().map(|b| format!("{:02x}", b)).collect())
+        }
+
+        pub fn decode(token: &str) -> Res<Self> {
+            if !token.is_ascii() || !token.len().is_multiple_of(2) {
+                return Err(Error::InvalidCursor);
+            }
+            let bytes = (0..token.len()).step_by(2)
+                .map(|i| u8::from_str_radix(&token[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| Error::InvalidCursor)?;
+            let text = String::from_utf8(bytes).map_err(|_| Error::InvalidCursor)?;
+            json::parse(&text).map_err(|_| Error::InvalidCursor)
+        }
     }
 
     impl ItemCondition for StartIdCondition {
@@ -457,4 +1722,40 @@ pub mod condition {
             Ok(())
         }
     }
+
+    impl ItemCondition for BoundingBoxCondition {
+        fn apply(&self, tun: &mut ItemTun) -> Res<()> {
+            let points = tun.item.select_geo(tun.repo_id, self.min_lat, self.max_lat, self.min_lng, self.max_lng)?;
+            tun.intersect(points.into_iter().map(|point| point.item_id).collect());
+            Ok(())
+        }
+    }
+
+    impl ItemCondition for RadiusCondition {
+        fn apply(&self, tun: &mut ItemTun) -> Res<()> {
+            // the index narrows down to the enclosing box, the exact distance does the rest
+            let (min_lat, max_lat, min_lng, max_lng) = geo::bounding_box(self.latitude, self.longitude, self.radius);
+            let points = tun.item.select_geo(tun.repo_id, min_lat, max_lat, min_lng, max_lng)?;
+            tun.intersect(points.into_iter()
+                .filter(|point| geo::distance(self.latitude, self.longitude, point.latitude, point.longitude) <= self.radius)
+                .map(|point| point.item_id)
+                .collect());
+            Ok(())
+        }
+    }
+
+    impl ItemCondition for SearchCondition {
+        fn apply(&self, tun: &mut ItemTun) -> Res<()> {
+            let item_id_list = tun.item.search(tun.repo_id, &self.keyword)?;
+            tun.id_list = Some(match &tun.id_list {
+                None => item_id_list,
+                Some(id_list) => {
+                    let id_set = id_list.iter().collect::<HashSet<_>>();
+                    item_id_list.into_iter().filter(|id| id_set.contains(id)).collect()
+                }
+            });
+            tun.ranked = true;
+            Ok(())
+        }
+    }
 }
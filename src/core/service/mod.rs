@@ -4,6 +4,7 @@ pub mod item;
 pub mod tag;
 
 use crate::common::{Error, Res};
+use crate::core::repository::UnitOfWork;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -16,8 +17,8 @@ pub use user::UserService;
 pub use crate::core::Config;
 
 pub use crate::core::manager::{UserRole, User};
-pub use crate::core::manager::{CommonConfig, Repo, RepoConfig, RepoFileOrder, IllustrationConfig};
-pub use crate::core::manager::{Tag, MarkedTag};
+pub use crate::core::manager::RepoConfig;
+pub use crate::core::manager::MarkedTag;
 
 // region Service for all service
 
@@ -50,6 +51,52 @@ impl Clone for Service {
     }
 }
 
+// region batch
+
+// the most items one batch call may touch
+const MAX_BATCH_SIZE: usize = 1000;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct BatchResult {
+    // false as soon as one item failed, nothing of the batch is kept then
+    pub applied: bool,
+    pub items: Vec<BatchItem>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct BatchItem {
+    pub id: i64,
+    pub error: Option<String>,
+}
+
+// every item is tried so the report covers all of them, a single failure rolls the whole batch back
+fn batch<F>(unit: &Arc<dyn UnitOfWork>, ids: &[i64], mut f: F) -> Res<BatchResult>
+where
+    F: FnMut(i64) -> Res<()>,
+{
+    if ids.len() > MAX_BATCH_SIZE {
+        return Err(Error::BatchTooLarge(MAX_BATCH_SIZE));
+    }
+    let mut items = Vec::with_capacity(ids.len());
+    let result = unit.transaction(|| {
+        for id in ids {
+            items.push(BatchItem { id: *id, error: f(*id).err().map(|e| e.to_string()) });
+        }
+        if items.iter().any(|item| item.error.is_some()) {
+            Err(Error::BatchFailed)
+        } else {
+            Ok(())
+        }
+    });
+    match result {
+        Ok(()) => Ok(BatchResult { applied: true, items }),
+        Err(Error::BatchFailed) => Ok(BatchResult { applied: false, items }),
+        Err(e) => Err(e),
+    }
+}
+
+// endregion
+
 // 
 
 pub(in crate::core::service) struct UserContext {
@@ -152,3 +199,142 @@ where
         res
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{json, DirNode, Node};
+    use crate::core::manager::Repo;
+    use crate::core::service::item::condition::{ItemCondition, ItemOrder, SearchCondition};
+    use crate::core::service::item::ListOptions;
+    use crate::core::DatabaseSetting;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use std::io::Cursor;
+    use std::sync::{mpsc, Barrier};
+    use std::thread;
+
+    // services over the memory store, logged in as an admin on this thread, the files go to a fresh directory in /tmp
+    pub(super) fn service() -> (Service, Arc<dyn UnitOfWork>, DirNode) {
+        let root = test_root();
+        start(Config::in_memory(root.clone(), 1024 * 1024), root)
+    }
+
+    // the same over a sqlite database in that directory, with every migration applied
+    pub(super) fn sqlite_service() -> (Service, Arc<dyn UnitOfWork>, DirNode) {
+        let root = test_root();
+        start(Config::new(root.clone(), 1024 * 1024, DatabaseSetting::default()).unwrap(), root)
+    }
+
+    fn test_root() -> DirNode {
+        DirNode::ROOT().next(String::from("tmp")).next(format!("vines-test-{}", Uuid::new_v4()))
+    }
+
+    fn start(config: Config, root: DirNode) -> (Service, Arc<dyn UnitOfWork>, DirNode) {
+        let user = config.user_manager.create_user(String::from("ann"), "pw").unwrap();
+        config.user_manager.update_user_role(user.id, 0, UserRole::Admin).unwrap();
+        let unit = config.unit.clone();
+        let service = Service::new(config);
+        service.user.login("ann", "pw").unwrap();
+        (service, unit, root)
+    }
+
+    pub(super) fn create_repo(service: &Service) -> Repo {
+        let config = json::parse(r#"{"Illustration": {"common_config": {"order": "CreateYearTime"}}}"#).unwrap();
+        let repo = service.repo.create(String::from("r1"), config).unwrap();
+        // the role on the new repo is picked up the way every request does it
+        service.user.refresh(&service.user.get_session_key()).unwrap();
+        repo
+    }
+
+    pub(super) fn create_item(service: &Service, repo_id: i64, shade: u8) -> i64 {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 8, image::Rgb([shade, 0, 0])))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        let mut upload = service.item.begin_upload(repo_id).unwrap();
+        upload.write(&data).unwrap();
+        service.item.create(upload, format!("{}.png", shade), None).unwrap().id
+    }
+
+    fn list_all(service: &Service, repo_id: i64, order: ItemOrder, condition: Option<Vec<Box<dyn ItemCondition>>>) -> Vec<i64> {
+        let mut options = ListOptions { limit: 2, from_big: true, order, seed: None, scan: None, cursor: None, count: false };
+        let mut ids = Vec::new();
+        loop {
+            let page = service.item.select_list(repo_id, &options, &condition, &None).unwrap();
+            ids.extend(page.items.iter().map(|item| item.id));
+            match page.cursor {
+                Some(cursor) => options.cursor = Some(cursor),
+                None => return ids,
+            }
+        }
+    }
+
+    #[test]
+    fn rollback_keeps_writes_of_other_threads() {
+        let (service, unit, root) = service();
+        // the token of the login, refreshing hands the context a new one that is never stored
+        let token = service.user.get_session_key();
+        let repo = create_repo(&service);
+
+        let transaction = unit.begin().unwrap();
+        service.tag.create(String::from("rolled back"), repo.id, 0).unwrap();
+        let other = service.clone();
+        let started = Arc::new(Barrier::new(2));
+        let (written, done) = mpsc::channel();
+        let writer = {
+            let started = started.clone();
+            thread::spawn(move || {
+                other.user.refresh(&token).unwrap();
+                started.wait();
+                other.tag.create(String::from("kept"), repo.id, 0).unwrap();
+                written.send(()).unwrap();
+            })
+        };
+        // the other thread writes while the transaction is still open, it has to wait for it
+        started.wait();
+        assert!(done.try_recv().is_err());
+        transaction.rollback().unwrap();
+        done.recv().unwrap();
+        writer.join().unwrap();
+
+        let names: Vec<String> = service.tag.list(repo.id).unwrap().into_iter().map(|tag| tag.name).collect();
+        assert_eq!(names, vec![String::from("kept")]);
+        let _ = std::fs::remove_dir_all(root.absolute_path());
+    }
+
+    #[test]
+    fn sqlite_lists_only_live_items() {
+        let (service, _, root) = sqlite_service();
+        let repo = create_repo(&service);
+        let ids: Vec<i64> = (1..=5).map(|shade| create_item(&service, repo.id, shade * 40)).collect();
+        let tag = service.tag.create(String::from("sunset"), repo.id, 0).unwrap();
+        service.tag.apply_tag(ids[1], tag.id).unwrap();
+        service.tag.apply_tag(ids[3], tag.id).unwrap();
+        service.item.delete(ids[3]).unwrap();
+
+        // paged two at a time, every live item exactly once
+        let mut expected: Vec<i64> = ids.iter().copied().filter(|id| *id != ids[3]).collect();
+        expected.reverse();
+        assert_eq!(list_all(&service, repo.id, ItemOrder::Id, None), expected);
+        for order in [ItemOrder::Name, ItemOrder::Size, ItemOrder::Area, ItemOrder::AspectRatio, ItemOrder::TakenAt, ItemOrder::TagCount, ItemOrder::Random] {
+            let mut listed = list_all(&service, repo.id, order, None);
+            listed.sort_unstable_by(|a, b| b.cmp(a));
+            assert_eq!(listed, expected);
+        }
+        // the only tagged item that is still live leads the order by tags
+        let by_tags = list_all(&service, repo.id, ItemOrder::TagCount, None);
+        assert_eq!(by_tags[0], ids[1]);
+
+        // the tag reaches the search index through the triggers, the deleted item drops out of it
+        let search = || Some(vec![Box::new(SearchCondition { keyword: String::from("sunset") }) as Box<dyn ItemCondition>]);
+        assert_eq!(list_all(&service, repo.id, ItemOrder::Id, search()), vec![ids[1]]);
+        let trash: Vec<i64> = service.item.select_trash(repo.id, None, 10).unwrap().into_iter().map(|item| item.id).collect();
+        assert_eq!(trash, vec![ids[3]]);
+
+        service.item.restore(ids[3]).unwrap();
+        let mut found = list_all(&service, repo.id, ItemOrder::Id, search());
+        found.sort_unstable();
+        assert_eq!(found, vec![ids[1], ids[3]]);
+        let _ = std::fs::remove_dir_all(root.absolute_path());
+    }
+}
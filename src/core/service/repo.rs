@@ -27,6 +27,7 @@ impl RepoService {
 
     pub fn create(&self, name: String, config: RepoConfig) -> Res<Repo> {
         check_permission(0, UserRole::Manager)?;
+        config.common_config()?;
         with_context(|ctx| {
             match self.repo.select_repo_by_name(&name) {
                 Ok(_) => Err(Error::UsedRepoName),
@@ -1,10 +1,12 @@
 use crate::common::{Error, Res};
 use crate::core::manager::tag::TagManager;
 use crate::core::manager::{Config, ItemManager, MarkedTag, Tag};
-use crate::core::service::{check_permission, get_user_id, UserRole};
+use crate::core::repository::UnitOfWork;
+use crate::core::service::{batch, check_permission, get_user_id, BatchResult, UserRole};
 use std::sync::Arc;
 
 pub struct TagService {
+    unit: Arc<dyn UnitOfWork>,
     item: Arc<ItemManager>,
     tag: Arc<TagManager>,
 }
@@ -12,6 +14,7 @@ pub struct TagService {
 impl TagService {
     pub fn new(config: &Config) -> Self {
         Self {
+            unit: config.unit.clone(),
             item: config.item_manager.clone(),
             tag: config.tag_manager.clone(),
         }
@@ -22,6 +25,8 @@ impl TagService {
     }
 
     pub fn list_item(&self, item_id: i64) -> Res<Vec<MarkedTag>> {
+        let item = self.item.select_live_by_id(item_id)?;
+        check_permission(item.repo_id, UserRole::Viewer)?;
         self.tag.select_item_tag(item_id)
     }
 
@@ -33,10 +38,18 @@ impl TagService {
     pub fn delete(&self, id: i64) -> Res<()> {
         let tag = self.tag.select_by_id(id)?;
         check_permission(tag.repo_id, UserRole::Manager)?;
-        self.tag.delete(tag.id)?;
+        self.unit.transaction(|| self.tag.delete(tag.id))?;
         Ok(())
     }
 
+    // the relations the delete took away come back with the tag
+    pub fn restore(&self, id: i64) -> Res<Tag> {
+        let tag = self.tag.select_deleted_by_id(id)?;
+        check_permission(tag.repo_id, UserRole::Manager)?;
+        self.unit.transaction(|| self.tag.reset(tag.id))?;
+        self.tag.select_by_id(id)
+    }
+
     pub fn change_parent(&self, id: i64, parent: i64) -> Res<()> {
         let mut tag = self.tag.select_by_id(id)?;
         let parent_tag = self.tag.select_by_id(parent)?;
@@ -73,7 +86,7 @@ impl TagService {
 
     pub fn apply_tag(&self, item_id: i64, tag_id: i64) -> Res<()> {
         let tag = self.tag.select_by_id(tag_id)?;
-        let item = self.item.select_by_id(item_id)?;
+        let item = self.item.select_live_by_id(item_id)?;
         let repo_id = item.repo_id;
         if repo_id != tag.repo_id {
             Err(Error::TagNotFound)
@@ -85,7 +98,7 @@ impl TagService {
     }
 
     pub fn remove_tag(&self, item_id: i64, tag_id: i64) -> Res<()> {
-        let item = self.item.select_by_id(item_id)?;
+        let item = self.item.select_live_by_id(item_id)?;
         let marked_tag = self.tag.select_marked_tag(tag_id, item_id)?;
         let user_id = get_user_id()?;
         if marked_tag.creator == user_id {
@@ -96,4 +109,68 @@ impl TagService {
             self.tag.remove_tag(tag_id, item_id)
         }
     }
+
+    pub fn batch_apply_tag(&self, item_ids: &[i64], tag_id: i64) -> Res<BatchResult> {
+        batch(&self.unit, item_ids, |item_id| self.apply_tag(item_id, tag_id))
+    }
+
+    pub fn batch_remove_tag(&self, item_ids: &[i64], tag_id: i64) -> Res<BatchResult> {
+        batch(&self.unit, item_ids, |item_id| self.remove_tag(item_id, tag_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::Node;
+    use crate::core::service::tests::{create_item, create_repo, service, sqlite_service};
+    use crate::core::service::Service;
+
+    fn failed_batch_keeps_nothing(service: Service) {
+        let repo = create_repo(&service);
+        let (a, b) = (create_item(&service, repo.id, 10), create_item(&service, repo.id, 200));
+        let tag = service.tag.create(String::from("t"), repo.id, 0).unwrap();
+
+        let result = service.tag.batch_apply_tag(&[a, b, -1], tag.id).unwrap();
+        assert!(!result.applied);
+        assert!(result.items[2].error.is_some());
+        assert!(service.tag.list_item(a).unwrap().is_empty());
+
+        let result = service.tag.batch_apply_tag(&[a, b], tag.id).unwrap();
+        assert!(result.applied);
+        assert_eq!(service.tag.list_item(b).unwrap().len(), 1);
+    }
+
+    fn apply_is_idempotent(service: Service) {
+        let repo = create_repo(&service);
+        let item = create_item(&service, repo.id, 10);
+        let tag = service.tag.create(String::from("t"), repo.id, 0).unwrap();
+
+        assert!(service.tag.batch_apply_tag(&[item, item], tag.id).unwrap().applied);
+        service.tag.apply_tag(item, tag.id).unwrap();
+        assert_eq!(service.tag.list_item(item).unwrap().len(), 1);
+
+        // once removed the relation is gone for good, a second removal has nothing to find
+        assert!(service.tag.batch_remove_tag(&[item], tag.id).unwrap().applied);
+        assert!(!service.tag.batch_remove_tag(&[item], tag.id).unwrap().applied);
+        service.tag.apply_tag(item, tag.id).unwrap();
+        assert!(service.tag.batch_remove_tag(&[item], tag.id).unwrap().applied);
+    }
+
+    #[test]
+    fn batches_on_memory() {
+        for test in [failed_batch_keeps_nothing, apply_is_idempotent] {
+            let (service, _, root) = service();
+            test(service);
+            let _ = std::fs::remove_dir_all(root.absolute_path());
+        }
+    }
+
+    #[test]
+    fn batches_on_sqlite() {
+        for test in [failed_batch_keeps_nothing, apply_is_idempotent] {
+            let (service, _, root) = sqlite_service();
+            test(service);
+            let _ = std::fs::remove_dir_all(root.absolute_path());
+        }
+    }
 }
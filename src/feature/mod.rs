@@ -1 +1,2 @@
+pub mod trash;
 pub mod web;
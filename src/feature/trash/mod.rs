@@ -0,0 +1,52 @@
+use crate::common::Res;
+use crate::core::service::Service;
+use log::{error, info};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// retention is counted in days, a few runs a day keep the trash within hours of it,
+// upload sessions are swept on the same runs
+const PURGE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+// how soon a queued backfill is picked up, the thread does not sleep while one is going on
+const BACKFILL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub fn init(service: Service) -> std::io::Result<()> {
+    let mut purged_at: Option<Instant> = None;
+    loop {
+        if purged_at.is_none_or(|at| at.elapsed() >= PURGE_INTERVAL) {
+            match run("purge trash", || service.item.purge_expired()) {
+                0 => {}
+                count => info!("purged {} items out of the trash", count),
+            }
+            match run("sweep upload sessions", || service.item.sweep_upload_sessions()) {
+                0 => {}
+                count => info!("dropped {} idle upload sessions", count),
+            }
+            purged_at = Some(Instant::now());
+        }
+        match run("backfill extend", || service.item.backfill_batch()) {
+            0 => {}
+            count => info!("read the extends of {} items again", count),
+        }
+        if !service.item.backfill_pending().unwrap_or(false) {
+            thread::sleep(BACKFILL_INTERVAL);
+        }
+    }
+}
+
+// a panic only loses this run of the task, the thread keeps going for the next one
+fn run<F: FnOnce() -> Res<usize>>(task: &str, f: F) -> usize {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(count)) => count,
+        Ok(Err(e)) => {
+            error!("{} failed: {}", task, e);
+            0
+        }
+        Err(_) => {
+            error!("{} panicked", task);
+            0
+        }
+    }
+}
@@ -1,11 +1,16 @@
 use crate::common::result::to_response;
-use crate::common::{json, Res};
-use crate::core::service::item::condition::{EndIdCondition, EndTimeCondition, ItemCondition, StartIdCondition, StartTimeCondition, TagCondition};
-use crate::core::service::item::filter::{ItemFilter, RectangleFilter, SizeFilter, UrlFilter};
+use crate::common::{json, ContentType, Error, FileType, Fit, RenditionFormat, Res};
+use crate::core::service::item::condition::{BoundingBoxCondition, EndIdCondition, RadiusCondition, EndTimeCondition, ItemCondition, ItemOrder, StartIdCondition, SearchCondition, StartTimeCondition, TagCondition};
+use crate::core::service::item::filter::{CameraFilter, ColorFilter, ItemFilter, RectangleFilter, SizeFilter, TakenTimeFilter, UrlFilter};
+use crate::core::service::item::{ItemContent, ListOptions};
 use crate::core::service::{ItemService, MarkedTag, TagService};
-use actix_web::web::{Data, Query};
-use actix_web::{web::Bytes, HttpResponse, Responder};
+use actix_web::http::header::{ByteRangeSpec, Charset, ContentDisposition, ContentRange, ContentRangeSpec, DispositionParam, DispositionType, ETag, EntityTag, ExtendedValue, Header, IfModifiedSince, IfNoneMatch, IfRange, LastModified, Range, ACCEPT_RANGES, CONTENT_TYPE, IF_NONE_MATCH, IF_RANGE, X_CONTENT_TYPE_OPTIONS};
+use actix_web::http::StatusCode;
+use actix_web::web::{block, Data, Json, Payload, Query};
+use actix_web::{HttpRequest, HttpResponse, Responder};
 use either::Either;
+use futures_util::StreamExt;
+use std::time::SystemTime;
 
 pub(super) async fn get(item: Data<ItemService>, request: Query<GetRequest>) -> impl Responder {
     to_response(item.select_by_id(request.id))
@@ -19,22 +24,51 @@ pub(super) async fn get_extend(tag: Data<TagService>, request: Query<GetRequest>
     to_response(query(tag, request.id))
 }
 
-pub(super) async fn read(item: Data<ItemService>, request: Query<GetRequest>) -> impl Responder {
+pub(super) async fn read(item: Data<ItemService>, request: Query<GetRequest>, http: HttpRequest) -> impl Responder {
     let result = item.read_item(request.id);
     match result {
-        Ok(stream) => HttpResponse::Ok().streaming(stream),
+        Ok(content) => content_response(&http, content),
         Err(e) => e.to_response()
     }
 }
 
-pub(super) async fn read_thumbnail(item: Data<ItemService>, request: Query<GetRequest>) -> impl Responder {
-    let result = item.read_thumbnail(request.id);
+pub(super) async fn read_thumbnail(item: Data<ItemService>, request: Query<ThumbnailRequest>, http: HttpRequest) -> impl Responder {
+    let result = item.read_thumbnail(request.id, request.animated);
     match result {
-        Ok(stream) => HttpResponse::Ok().streaming(stream),
+        Ok(content) => content_response(&http, content),
         Err(e) => e.to_response()
     }
 }
 
+pub(super) async fn read_rendition(item: Data<ItemService>, request: Query<RenditionRequest>, http: HttpRequest) -> impl Responder {
+    let result = item.read_rendition(request.id, request.width, request.height, request.fit, request.format);
+    match result {
+        Ok(content) => content_response(&http, content),
+        Err(e) => e.to_response()
+    }
+}
+
+pub(super) async fn read_frame(item: Data<ItemService>, request: Query<FrameRequest>, http: HttpRequest) -> impl Responder {
+    let result = item.read_frame(request.id, request.index, request.format);
+    match result {
+        Ok(content) => content_response(&http, content),
+        Err(e) => e.to_response()
+    }
+}
+
+pub(super) async fn similar(item: Data<ItemService>, request: Query<SimilarRequest>) -> impl Responder {
+    to_response(item.select_similar(request.id, request.distance))
+}
+
+pub(super) async fn duplicate_groups(item: Data<ItemService>, request: Query<DuplicateGroupsRequest>) -> impl Responder {
+    to_response(item.select_duplicate_groups(request.repo_id, request.distance))
+}
+
+pub(super) async fn geo_cluster(item: Data<ItemService>, request: Query<GeoClusterRequest>) -> impl Responder {
+    let GeoClusterRequest { repo_id, min_lat, max_lat, min_lng, max_lng, grid } = request.0;
+    to_response(item.geo_cluster(repo_id, min_lat, max_lat, min_lng, max_lng, grid))
+}
+
 pub(super) async fn list(item: Data<ItemService>, request: Query<ItemListRequest>) -> impl Responder {
     let (condition_list, filter_list) = match &request.condition {
         None => (None, None),
@@ -54,12 +88,109 @@ pub(super) async fn list(item: Data<ItemService>, request: Query<ItemListRequest
         }
     };
 
-    to_response(item.select_list(request.repo_id, request.limit, request.from_big, &condition_list, &filter_list))
+    let options = ListOptions {
+        limit: request.limit,
+        from_big: request.from_big,
+        order: request.order.unwrap_or_default(),
+        seed: request.seed,
+        scan: request.scan,
+        cursor: request.cursor.clone(),
+        count: request.count,
+    };
+    to_response(item.select_list(request.repo_id, &options, &condition_list, &filter_list))
+}
+
+pub(super) async fn create(item: Data<ItemService>, query: Query<CreateRequest>, mut payload: Payload) -> impl Responder {
+    let mut upload = match item.begin_upload(query.repo_id) {
+        Ok(upload) => upload,
+        Err(e) => return e.to_response()
+    };
+    while let Some(chunk) = payload.next().await {
+        let result = chunk.map_err(|e| Error::Common(e.to_string())).and_then(|chunk| upload.write(&chunk));
+        if let Err(e) = result {
+            return e.to_response();
+        }
+    }
+    // decoding and analysing the image would hold up the worker
+    let CreateRequest { name, caption, .. } = query.into_inner();
+    let result = block(move || item.create(upload, name, caption)).await;
+    to_response(result.unwrap_or_else(|e| Err(Error::Common(e.to_string()))))
+}
+
+pub(super) async fn create_upload(item: Data<ItemService>, request: Json<CreateUploadRequest>) -> impl Responder {
+    let CreateUploadRequest { repo_id, name, caption, size } = request.0;
+    to_response(item.create_upload_session(repo_id, name, caption, size))
+}
+
+pub(super) async fn get_upload(item: Data<ItemService>, request: Query<UploadRequest>) -> impl Responder {
+    to_response(item.select_upload_session(request.repo_id, &request.id))
+}
+
+pub(super) async fn patch_upload(item: Data<ItemService>, request: Query<PatchUploadRequest>, mut payload: Payload) -> impl Responder {
+    let mut writer = match item.write_upload_session(request.repo_id, &request.id, request.offset) {
+        Ok(writer) => writer,
+        Err(e) => return e.to_response()
+    };
+    // whatever arrived before a disconnect stays staged, the client resumes from the reported offset
+    while let Some(chunk) = payload.next().await {
+        let result = chunk.map_err(|e| Error::Common(e.to_string())).and_then(|chunk| writer.write(&chunk));
+        if let Err(e) = result {
+            return e.to_response();
+        }
+    }
+    to_response(Ok(writer.session()))
+}
+
+pub(super) async fn finish_upload(item: Data<ItemService>, request: Json<UploadRequest>) -> impl Responder {
+    to_response(item.finish_upload_session(request.repo_id, &request.id))
+}
+
+pub(super) async fn backfill_extend(item: Data<ItemService>, request: Json<BackfillRequest>) -> impl Responder {
+    to_response(item.backfill_extend(request.repo_id, request.start_id))
+}
+
+pub(super) async fn get_backfill(item: Data<ItemService>, request: Query<RepoRequest>) -> impl Responder {
+    to_response(item.select_backfill(request.repo_id))
 }
 
-pub(super) async fn create(item: Data<ItemService>, query: Query<CreateRequest>, body: Bytes) -> impl Responder {
-    let result = item.create(query.repo_id, query.name.clone(), &body.to_vec());
-    to_response(result)
+pub(super) async fn delete(item: Data<ItemService>, request: Json<GetRequest>) -> impl Responder {
+    to_response(item.delete(request.id))
+}
+
+pub(super) async fn restore(item: Data<ItemService>, request: Json<GetRequest>) -> impl Responder {
+    to_response(item.restore(request.id))
+}
+
+pub(super) async fn trash(item: Data<ItemService>, request: Query<TrashRequest>) -> impl Responder {
+    to_response(item.select_trash(request.repo_id, request.end_id, request.limit))
+}
+
+pub(super) async fn purge_trash(item: Data<ItemService>, request: Json<RepoRequest>) -> impl Responder {
+    to_response(item.purge_trash(request.repo_id))
+}
+
+pub(super) async fn change_repo(item: Data<ItemService>, request: Json<ChangeRepoRequest>) -> impl Responder {
+    to_response(item.change_repo(request.id, request.repo_id))
+}
+
+pub(super) async fn batch_delete(item: Data<ItemService>, request: Json<BatchRequest>) -> impl Responder {
+    to_response(item.batch_delete(&request.ids))
+}
+
+pub(super) async fn batch_restore(item: Data<ItemService>, request: Json<BatchRequest>) -> impl Responder {
+    to_response(item.batch_restore(&request.ids))
+}
+
+pub(super) async fn batch_change_repo(item: Data<ItemService>, request: Json<BatchChangeRepoRequest>) -> impl Responder {
+    to_response(item.batch_change_repo(&request.ids, request.repo_id))
+}
+
+pub(super) async fn batch_patch_extend(item: Data<ItemService>, request: Json<BatchPatchExtendRequest>) -> impl Responder {
+    to_response(item.batch_patch_extend(&request.ids, &request.patch))
+}
+
+pub(super) async fn update_caption(item: Data<ItemService>, request: Json<UpdateCaptionRequest>) -> impl Responder {
+    to_response(item.update_caption(request.id, request.caption.clone()))
 }
 
 
@@ -68,10 +199,167 @@ pub(super) struct GetRequest {
     id: i64,
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct RepoRequest {
+    repo_id: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct BackfillRequest {
+    repo_id: i64,
+    start_id: Option<i64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct SimilarRequest {
+    id: i64,
+    #[serde(default = "default_similar_distance")]
+    distance: u32,
+}
+
+fn default_similar_distance() -> u32 {
+    10
+}
+
+// a tighter default than similar, a group is meant to be the same picture
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct DuplicateGroupsRequest {
+    repo_id: i64,
+    #[serde(default = "default_duplicate_distance")]
+    distance: u32,
+}
+
+fn default_duplicate_distance() -> u32 {
+    4
+}
+
+// the whole world in 32 x 32 cells unless asked otherwise
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct GeoClusterRequest {
+    repo_id: i64,
+    #[serde(default = "default_min_lat")]
+    min_lat: f64,
+    #[serde(default = "default_max_lat")]
+    max_lat: f64,
+    #[serde(default = "default_min_lng")]
+    min_lng: f64,
+    #[serde(default = "default_max_lng")]
+    max_lng: f64,
+    #[serde(default = "default_grid")]
+    grid: u32,
+}
+
+fn default_min_lat() -> f64 {
+    -90.0
+}
+
+fn default_max_lat() -> f64 {
+    90.0
+}
+
+fn default_min_lng() -> f64 {
+    -180.0
+}
+
+fn default_max_lng() -> f64 {
+    180.0
+}
+
+fn default_grid() -> u32 {
+    32
+}
+
+// without animated an animation gets its first frame
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct ThumbnailRequest {
+    id: i64,
+    #[serde(default)]
+    animated: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct FrameRequest {
+    id: i64,
+    index: u32,
+    #[serde(default)]
+    format: RenditionFormat,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct RenditionRequest {
+    id: i64,
+    width: u32,
+    height: u32,
+    #[serde(default)]
+    fit: Fit,
+    #[serde(default)]
+    format: RenditionFormat,
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub(super) struct CreateRequest {
     repo_id: i64,
     name: String,
+    caption: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct CreateUploadRequest {
+    repo_id: i64,
+    name: String,
+    caption: Option<String>,
+    size: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct UploadRequest {
+    repo_id: i64,
+    id: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct PatchUploadRequest {
+    repo_id: i64,
+    id: String,
+    offset: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct TrashRequest {
+    repo_id: i64,
+    // the smallest id of the previous page
+    end_id: Option<i64>,
+    limit: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct ChangeRepoRequest {
+    id: i64,
+    repo_id: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct BatchRequest {
+    ids: Vec<i64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct BatchChangeRepoRequest {
+    ids: Vec<i64>,
+    repo_id: i64,
+}
+
+// a json merge patch applied to the extend of every item
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct BatchPatchExtendRequest {
+    ids: Vec<i64>,
+    patch: serde_json::Value,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct UpdateCaptionRequest {
+    id: i64,
+    caption: Option<String>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -81,9 +369,15 @@ pub(super) enum ItemListType {
     StartTime,
     EndTime,
     Tag,
+    Search,
     Size,
     Rectangle,
     Url,
+    Camera,
+    TakenTime,
+    BoundingBox,
+    Radius,
+    Color,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -97,7 +391,14 @@ pub(super) struct ItemListRequest {
     repo_id: i64,
     limit: i64,
     from_big: bool,
-    condition: Option<Vec<ItemListCondition>>
+    condition: Option<Vec<ItemListCondition>>,
+    order: Option<ItemOrder>,
+    seed: Option<u64>,
+    // the cursor of the previous page
+    cursor: Option<String>,
+    scan: Option<i64>,
+    #[serde(default)]
+    count: bool,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -105,6 +406,97 @@ pub(super) struct ExtendItemResponse {
     tags: Vec<MarkedTag>,
 }
 
+// conditional requests are answered first, then a single byte range, several ranges get the whole file
+fn content_response(request: &HttpRequest, content: ItemContent) -> HttpResponse {
+    let etag = EntityTag::new_strong(content.etag.clone());
+    let modified = SystemTime::from(content.modified);
+    if is_not_modified(request, &etag, modified) {
+        return HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .insert_header(LastModified(modified.into()))
+            .finish();
+    }
+
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(ETag(etag.clone()))
+        .insert_header(LastModified(modified.into()))
+        .insert_header((ACCEPT_RANGES, "bytes"))
+        .insert_header((CONTENT_TYPE, content.content_type.mimetype))
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header(content_disposition(content.name.clone(), content.content_type));
+
+    let (start, len) = match requested_range(request, &etag, modified) {
+        None => (0, content.size),
+        Some(spec) => match spec.to_satisfiable_range(content.size) {
+            None => {
+                return HttpResponse::RangeNotSatisfiable()
+                    .insert_header(ContentRange(ContentRangeSpec::Bytes { range: None, instance_length: Some(content.size) }))
+                    .finish();
+            }
+            Some((start, end)) => {
+                response.status(StatusCode::PARTIAL_CONTENT)
+                    .insert_header(ContentRange(ContentRangeSpec::Bytes { range: Some((start, end)), instance_length: Some(content.size) }));
+                (start, end - start + 1)
+            }
+        }
+    };
+
+    match content.file.as_range_stream(start, len) {
+        Ok(stream) => response.no_chunking(len).streaming(stream),
+        Err(e) => e.to_response()
+    }
+}
+
+fn is_not_modified(request: &HttpRequest, etag: &EntityTag, modified: SystemTime) -> bool {
+    // If-Modified-Since only counts when there is no If-None-Match
+    if request.headers().contains_key(IF_NONE_MATCH) {
+        match IfNoneMatch::parse(request) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+            Err(_) => false,
+        }
+    } else {
+        match IfModifiedSince::parse(request) {
+            Ok(IfModifiedSince(since)) => modified <= SystemTime::from(since),
+            Err(_) => false,
+        }
+    }
+}
+
+fn requested_range(request: &HttpRequest, etag: &EntityTag, modified: SystemTime) -> Option<ByteRangeSpec> {
+    if request.headers().contains_key(IF_RANGE) {
+        let fresh = match IfRange::parse(request) {
+            Ok(IfRange::EntityTag(tag)) => tag.strong_eq(etag),
+            Ok(IfRange::Date(date)) => modified <= SystemTime::from(date),
+            Err(_) => false,
+        };
+        if !fresh {
+            return None;
+        }
+    }
+
+    match Range::parse(request) {
+        Ok(Range::Bytes(mut ranges)) if ranges.len() == 1 => ranges.pop(),
+        _ => None,
+    }
+}
+
+// an svg or a text opened on our origin could run its scripts there, those are only downloaded,
+// an <img> still shows an svg since the disposition only counts for navigation
+fn content_disposition(name: String, content_type: &ContentType) -> ContentDisposition {
+    let disposition = match content_type.file {
+        FileType::Vector | FileType::Plain => DispositionType::Attachment,
+        _ => DispositionType::Inline,
+    };
+    let parameter = if name.is_ascii() {
+        DispositionParam::Filename(name)
+    } else {
+        DispositionParam::FilenameExt(ExtendedValue { charset: Charset::Ext(String::from("UTF-8")), language_tag: None, value: name.into_bytes() })
+    };
+    ContentDisposition { disposition, parameters: vec![parameter] }
+}
+
 impl ItemListCondition {
     fn cast(&self) -> Res<Either<Box<dyn ItemCondition>, Box<dyn ItemFilter>>> {
         match self.key {
@@ -113,9 +505,15 @@ impl ItemListCondition {
             ItemListType::StartTime => Ok(Either::Left(Box::new(json::parse::<StartTimeCondition>(&self.value)?))),
             ItemListType::EndTime => Ok(Either::Left(Box::new(json::parse::<EndTimeCondition>(&self.value)?))),
             ItemListType::Tag => Ok(Either::Left(Box::new(json::parse::<TagCondition>(&self.value)?))),
+            ItemListType::Search => Ok(Either::Left(Box::new(json::parse::<SearchCondition>(&self.value)?))),
+            ItemListType::BoundingBox => Ok(Either::Left(Box::new(json::parse::<BoundingBoxCondition>(&self.value)?))),
+            ItemListType::Radius => Ok(Either::Left(Box::new(json::parse::<RadiusCondition>(&self.value)?))),
             ItemListType::Size => Ok(Either::Right(Box::new(json::parse::<SizeFilter>(&self.value)?))),
             ItemListType::Rectangle => Ok(Either::Right(Box::new(json::parse::<RectangleFilter>(&self.value)?))),
             ItemListType::Url => Ok(Either::Right(Box::new(json::parse::<UrlFilter>(&self.value)?))),
+            ItemListType::Camera => Ok(Either::Right(Box::new(json::parse::<CameraFilter>(&self.value)?))),
+            ItemListType::Color => Ok(Either::Right(Box::new(json::parse::<ColorFilter>(&self.value)?))),
+            ItemListType::TakenTime => Ok(Either::Right(Box::new(json::parse::<TakenTimeFilter>(&self.value)?))),
         }
     }
 }
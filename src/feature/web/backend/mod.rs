@@ -33,7 +33,6 @@ pub async fn init(service: Service) -> std::io::Result<()> {
                     res
                 }
             })
-            .app_data(web::PayloadConfig::new(100 * 1024 * 1024))
             .app_data(Data::from(service.repo.clone()))
             .app_data(Data::from(service.user.clone()))
             .app_data(Data::from(service.item.clone()))
@@ -59,7 +58,35 @@ pub async fn init(service: Service) -> std::io::Result<()> {
                     .route("/get_extend", web::get().to(item::get_extend))
                     .route("/read", web::get().to(item::read))
                     .route("/read_thumbnail", web::get().to(item::read_thumbnail))
+                    .route("/read_rendition", web::get().to(item::read_rendition))
+                    .route("/read_frame", web::get().to(item::read_frame))
+                    .route("/geo_cluster", web::get().to(item::geo_cluster))
+                    .route("/similar", web::get().to(item::similar))
+                    .route("/duplicate_groups", web::get().to(item::duplicate_groups))
                     .route("/create", web::post().to(item::create))
+                    .route("/update_caption", web::post().to(item::update_caption))
+                    .route("/delete", web::post().to(item::delete))
+                    .route("/restore", web::post().to(item::restore))
+                    .route("/change_repo", web::post().to(item::change_repo))
+                    .route("/trash", web::get().to(item::trash))
+                    .route("/purge_trash", web::post().to(item::purge_trash))
+                    .route("/backfill_extend", web::post().to(item::backfill_extend))
+                    .route("/backfill_extend/get", web::get().to(item::get_backfill))
+                    .route("/upload/create", web::post().to(item::create_upload))
+                    .route("/upload/get", web::get().to(item::get_upload))
+                    .route("/upload/patch", web::patch().to(item::patch_upload))
+                    .route("/upload/finish", web::post().to(item::finish_upload))
+                    .route("/batch/delete", web::post().to(item::batch_delete))
+                    .route("/batch/restore", web::post().to(item::batch_restore))
+                    .route("/batch/change_repo", web::post().to(item::batch_change_repo))
+                    .route("/batch/patch_extend", web::post().to(item::batch_patch_extend))
+            )
+            .service(
+                web::scope("/api/tag")
+                    .route("/delete", web::post().to(tag::delete))
+                    .route("/restore", web::post().to(tag::restore))
+                    .route("/batch/apply", web::post().to(tag::batch_apply))
+                    .route("/batch/remove", web::post().to(tag::batch_remove))
             )
     })
         .bind("127.0.0.1:8080")?
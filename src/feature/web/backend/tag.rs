@@ -1,5 +1,5 @@
 use actix_web::Responder;
-use actix_web::web::{Data, Query};
+use actix_web::web::{Data, Json, Query};
 use crate::common::result::to_response;
 use crate::core::service::TagService;
 
@@ -7,7 +7,34 @@ pub(super) async fn list(tag: Data<TagService>, request: Query<ListRequest>) ->
     to_response(tag.list(request.repo_id))
 }
 
+pub(super) async fn delete(tag: Data<TagService>, request: Json<GetRequest>) -> impl Responder {
+    to_response(tag.delete(request.id))
+}
+
+pub(super) async fn restore(tag: Data<TagService>, request: Json<GetRequest>) -> impl Responder {
+    to_response(tag.restore(request.id))
+}
+
+pub(super) async fn batch_apply(tag: Data<TagService>, request: Json<BatchTagRequest>) -> impl Responder {
+    to_response(tag.batch_apply_tag(&request.item_ids, request.tag_id))
+}
+
+pub(super) async fn batch_remove(tag: Data<TagService>, request: Json<BatchTagRequest>) -> impl Responder {
+    to_response(tag.batch_remove_tag(&request.item_ids, request.tag_id))
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub(super) struct ListRequest {
     repo_id: i64
-}
\ No newline at end of file
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct GetRequest {
+    id: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct BatchTagRequest {
+    item_ids: Vec<i64>,
+    tag_id: i64,
+}
@@ -1,6 +1,6 @@
 use crate::core::service::Service;
-use crate::core::Config;
-use crate::feature::web;
+use crate::core::{Config, DatabaseSetting, Synchronous};
+use crate::feature::{trash, web};
 use colored::Colorize;
 use env_logger::Builder;
 use std::collections::VecDeque;
@@ -19,10 +19,11 @@ fn main() {
 
 fn start_service() {
     let home = common::file::DirNode::ROOT().next(String::from("Users")).next(String::from("shiroha")).next(String::from("data"));
-    let config = Config::new(home, 4096);
+    let database = DatabaseSetting { wal: true, synchronous: Synchronous::Normal, ..DatabaseSetting::default() };
+    let config = Config::new(home, 4096, database).expect("Open database failed");
     let service = Service::new(config);
 
-    let mut inits = VecDeque::from(vec![web::init]);
+    let mut inits = VecDeque::from(vec![web::init, trash::init]);
     let mut threads = Vec::new();
     while let Some(init) = inits.pop_front() {
         let tmp_service = service.clone();